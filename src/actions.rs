@@ -0,0 +1,387 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+    process::exit,
+};
+
+use crate::{
+    printer::{eprint_line, Printer},
+    search::{file_len, read_bytes, ReadAt},
+};
+
+// The bytes of --extract from each match
+#[derive(Clone, Copy)]
+pub(crate) enum ExtractLength {
+    Bytes(u64),
+    // the matched bytes
+    Match,
+    // the bytes till the next match or the end of file
+    Auto,
+}
+
+impl ExtractLength {
+    // The length of the bytes extracted from each of the spans, which are in order of offsets
+    fn lengths(self, spans: &[Range<usize>]) -> Vec<u64> {
+        (0..spans.len())
+            .map(|i| match self {
+                ExtractLength::Bytes(len) => len,
+                ExtractLength::Match => spans[i].len() as u64,
+                // the last match is extracted till EOF rather than till the length of file,
+                // which is unknown for a pseudo-file like in /proc
+                ExtractLength::Auto => spans[i + 1..]
+                    .iter()
+                    .find(|next| next.start > spans[i].start)
+                    .map_or(u64::MAX, |next| (next.start - spans[i].start) as u64),
+            })
+            .collect()
+    }
+}
+
+// Where and how the matches are written by --extract
+pub(crate) struct Extraction<'a> {
+    pub(crate) length: ExtractLength,
+    // write each match to its own file in the directory, or else the raw bytes to the output
+    pub(crate) dir: Option<&'a Path>,
+    // the bytes written between the matches in the output
+    pub(crate) separator: Option<&'a [u8]>,
+    // the most files written to the directory, where 0 means no limit
+    pub(crate) max_extract: usize,
+    // overwrite the files existing in the directory
+    pub(crate) force: bool,
+    // the bytes copied to the output at once
+    pub(crate) chunk_size: usize,
+}
+
+impl Extraction<'_> {
+    // Extract the matches in the file of the path, where extracted counts the matches
+    // extracted from all files, and return whether all of them are extracted
+    pub(crate) fn extract(
+        &self,
+        printer: &Printer,
+        file: &File,
+        path: &str,
+        spans: &[Range<usize>],
+        extracted: &mut usize,
+    ) -> bool {
+        let lengths = self.length.lengths(spans);
+        let Some(dir) = self.dir else {
+            let mut chunk = vec![0; self.chunk_size];
+            for (span, length) in spans.iter().zip(lengths) {
+                if let Some(separator) = self.separator.filter(|_| *extracted > 0) {
+                    printer.write(separator);
+                }
+                let mut len = length as usize;
+                let mut at = span.start;
+                while len > 0 {
+                    let size = len.min(chunk.len());
+                    let read = (&*file).read_at(at, &mut chunk[..size]);
+                    if read == 0 {
+                        break;
+                    }
+                    printer.write(&chunk[..read]);
+                    (at, len) = (at + read, len - read);
+                }
+                *extracted += 1;
+            }
+            return true;
+        };
+        if let Err(err) = fs::create_dir_all(dir) {
+            printer.finish();
+            printer.error(&format!(
+                "Failed to create directory {}: {err}",
+                dir.display()
+            ));
+            exit(-1);
+        }
+        let basename = Path::new(path).file_name().map_or_else(
+            || path.to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let mut all = true;
+        for (span, len) in spans.iter().zip(lengths) {
+            if self.max_extract > 0 && *extracted >= self.max_extract {
+                printer.finish();
+                printer.error(&format!(
+                    "Stop extracting after {} files, which can be changed by --max-extract.",
+                    self.max_extract
+                ));
+                exit(-1);
+            }
+            let offset = span.start;
+            let name = format!("{basename}_{}.bin", printer.format_offset(offset));
+            let out = dir.join(name);
+            let written = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .create_new(!self.force)
+                .open(&out)
+                .and_then(|mut out| {
+                    (&*file).seek(SeekFrom::Start(offset as u64))?;
+                    io::copy(&mut file.take(len), &mut out)
+                });
+            match written {
+                Ok(written) => {
+                    *extracted += 1;
+                    printer.record(&format!(
+                        "{path}: {}: extracted {written} bytes to {}",
+                        printer.format_offset(offset),
+                        out.display()
+                    ));
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    printer.error(&format!(
+                        "Refuse to overwrite {}, which can be forced by --force.",
+                        out.display()
+                    ));
+                    all = false;
+                }
+                Err(err) => {
+                    printer.error(&format!("Failed to write {}: {err}", out.display()));
+                    all = false;
+                }
+            }
+        }
+        all
+    }
+}
+
+// How the matches are patched by --replace
+pub(crate) struct Replacement {
+    pub(crate) bytes: Vec<u8>,
+    // only print the bytes which would be replaced
+    pub(crate) dry_run: bool,
+    // copy the file to <file>.bak first, which isn't overwritten unless forced
+    pub(crate) backup: bool,
+    pub(crate) force: bool,
+}
+
+impl Replacement {
+    // Replace the bytes of the matches in the file of the path,
+    // and return whether the file is patched (or would be patched by --dry-run)
+    pub(crate) fn replace(
+        &self,
+        printer: &Printer,
+        file: &File,
+        path: &str,
+        spans: &[Range<usize>],
+    ) -> bool {
+        let replacement = &self.bytes;
+        // refuse to patch the file at all if any of the matches has another length
+        if let Some(span) = spans.iter().find(|span| span.len() != replacement.len()) {
+            printer.error(&format!(
+                "The replacement has {} bytes but the match at {} has {} bytes, skip patching {path}.",
+                replacement.len(),
+                printer.format_offset(span.start),
+                span.len()
+            ));
+            return false;
+        }
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        spans.iter().for_each(|span| {
+            let bytes = read_bytes(&mut &*file, span.start, replacement.len());
+            let action = if self.dry_run {
+                "would replace"
+            } else {
+                "replace"
+            };
+            printer.record(&format!(
+                "{path}: {}: {action} {} with {}",
+                printer.format_offset(span.start),
+                hex(&bytes),
+                hex(replacement)
+            ));
+        });
+        if self.dry_run {
+            return true;
+        }
+
+        if self.backup {
+            let backup = format!("{path}.bak");
+            // the backup of an earlier run may be the only original left
+            if Path::new(&backup).exists() && !self.force {
+                printer.error(&format!(
+                    "Refuse to overwrite {backup}, which can be forced by --force."
+                ));
+                return false;
+            }
+            if let Err(err) = fs::copy(path, &backup) {
+                printer.error(&format!("Failed to back up {path} to {backup}: {err}"));
+                return false;
+            }
+        }
+        let patched = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|mut file| {
+                spans.iter().try_for_each(|span| {
+                    file.seek(SeekFrom::Start(span.start as u64))?;
+                    file.write_all(replacement)
+                })
+            });
+        if let Err(err) = patched {
+            printer.error(&format!("Failed to patch {path}: {err}"));
+            return false;
+        }
+        true
+    }
+}
+
+// Compare two files line by line and print the lines where their bytes differ
+// with the lines of context, and return the number of the lines differing
+pub(crate) fn diff(
+    printer: &Printer,
+    paths: &[String],
+    line_width: usize,
+    buffer_size: usize,
+    (before, after): (usize, usize),
+    separator: &str,
+) -> usize {
+    let files = paths
+        .iter()
+        .map(|path| {
+            File::open(path).unwrap_or_else(|err| {
+                printer.error(&format!("Failed to open file {path}: {err}"));
+                exit(-1);
+            })
+        })
+        .collect::<Vec<_>>();
+    let filelen = files
+        .iter()
+        .map(|file| file_len(file).unwrap_or_default() as usize)
+        .max()
+        .unwrap_or_default();
+    printer.fit_offsets(filelen);
+    if printer.quiet == 0 {
+        printer.println(&format!(
+            "{}< {}\n> {}{}\n",
+            printer.style.bold, paths[0], paths[1], printer.style.reset
+        ));
+    }
+
+    // read both files in lines of line_width bytes, while the lines printed are read again
+    let mut readers = files
+        .iter()
+        .map(|file| BufReader::with_capacity(buffer_size, file))
+        .collect::<Vec<_>>();
+    let mut read_line = |i: usize| {
+        let mut line = Vec::new();
+        if let Err(err) = (&mut readers[i])
+            .take(line_width as u64)
+            .read_to_end(&mut line)
+        {
+            printer.error(&format!("Failed to read file {}: {err}", paths[i]));
+            exit(-1);
+        }
+        line
+    };
+    let mut differing = 0;
+    // the line printed last, and the identical lines still to print after a difference
+    let mut printed: Option<usize> = None;
+    let mut after_left = 0;
+    for line_offset in (0..).step_by(line_width) {
+        let (old, new) = (read_line(0), read_line(1));
+        if old.is_empty() && new.is_empty() {
+            break;
+        }
+        if old == new {
+            if after_left > 0 {
+                after_left -= 1;
+                printer.print("  ");
+                printer.read_and_print_one_line(&mut &files[0], line_width, line_offset, &[]);
+                printed = Some(line_offset);
+            }
+            continue;
+        }
+        differing += 1;
+        if printer.quiet > 0 {
+            break;
+        }
+        // the identical lines before the difference which haven't been printed
+        let first = printed.map_or(0, |printed| printed + line_width);
+        let start = line_offset.saturating_sub(before * line_width).max(first);
+        if printed.is_some() && start > first {
+            printer.println(separator);
+        }
+        for context_offset in (start..line_offset).step_by(line_width) {
+            printer.print("  ");
+            printer.read_and_print_one_line(&mut &files[0], line_width, context_offset, &[]);
+        }
+        // highlight the positions where the bytes differ, including those beyond the shorter line
+        let mut ranges: Vec<(Range<usize>, &str)> = Vec::new();
+        for i in (0..old.len().max(new.len())).filter(|i| old.get(*i) != new.get(*i)) {
+            match ranges.last_mut() {
+                Some((range, _)) if range.end == i => range.end = i + 1,
+                _ => ranges.push((i..i + 1, &printer.style.highlight)),
+            }
+        }
+        for (indicator, file) in ["< ", "> "].iter().zip(&files) {
+            printer.print(indicator);
+            if printer
+                .read_and_print_one_line(&mut &*file, line_width, line_offset, &ranges)
+                .is_empty()
+            {
+                printer.println("(EOF)");
+            }
+        }
+        printed = Some(line_offset);
+        after_left = after;
+    }
+    printer.flush();
+    if printer.quiet == 0 {
+        eprint_line(&format!("--- {differing} line(s) differ ---"));
+    }
+    differing
+}
+
+// Hex-dump the bytes in start..end of each file without searching,
+// and return whether all of the files are dumped
+pub(crate) fn dump(
+    printer: &Printer,
+    paths: &[String],
+    (start, end): (usize, usize),
+    line_width: usize,
+) -> bool {
+    if start >= end {
+        printer.error(&format!(
+            "The start {start} of --dump isn't less than the end {end}."
+        ));
+        exit(-1);
+    }
+    let mut dumped = true;
+    paths.iter().for_each(|path| {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) => {
+                printer.error(&format!("Failed to open file {path}: {err}"));
+                dumped = false;
+                return;
+            }
+        };
+        printer.fit_offsets(file_len(&file).unwrap_or_default() as usize);
+        printer.println(&format!(
+            "{}{path}{}:\n",
+            printer.style.bold, printer.style.reset
+        ));
+        for line_offset in (start..end).step_by(line_width) {
+            if printer
+                .read_and_print_bytes(&mut &file, line_width, line_offset, end - line_offset, &[])
+                .is_empty()
+            {
+                printer.println("(EOF)");
+                break;
+            }
+        }
+        printer.println("");
+    });
+    printer.flush();
+    dumped
+}
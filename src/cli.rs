@@ -0,0 +1,1310 @@
+use base64::{
+    alphabet::{Alphabet, STANDARD, URL_SAFE},
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use clap::{crate_version, value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use clap_complete::Shell;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self},
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use crate::{
+    actions::ExtractLength,
+    pattern::{parse_spaced_hex, parse_struct},
+    printer::{
+        eprint_line, DEFAULT_OUTPUT_FORMAT, DUMP_MATCH_FORMAT, LINE_OFFSET_ONLY_FORMAT,
+        ONLY_MATCHING_FORMAT,
+    },
+};
+
+// The widest line of the hexdump, which is already wider than most terminals
+pub(crate) const MAX_LINE_WIDTH: u8 = 64;
+// Arguments giving the patterns instead of the positional bytes,
+// and then all positional arguments are the files to search
+pub(crate) const PATTERN_ARGS: [&str; 13] = [
+    "pattern",
+    "pattern-file",
+    "needle-file",
+    "f32",
+    "f64",
+    "int8",
+    "int16",
+    "int32",
+    "int64",
+    "uuid",
+    "codepoint",
+    "base64-pattern",
+    "struct",
+];
+
+// -p can be given multiple times along with the patterns in --pattern-file,
+// otherwise the positional bytes is the only pattern, and with --dump there is no pattern,
+// so that the first positional argument is a file rather than the bytes
+pub(crate) fn explicit_patterns(matches: &ArgMatches) -> bool {
+    PATTERN_ARGS.iter().any(|id| matches.contains_id(id)) || matches.contains_id("dump")
+}
+
+// Parse the highlight color in comma-separated color names, bold, underline
+// or 256-color codes (e.g.: "bold,yellow" or "208") into an escape sequence
+pub(crate) fn parse_highlight_color(spec: &str) -> Result<String, String> {
+    const COLORS: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let codes = spec
+        .split(',')
+        .map(|style| {
+            let style = style.trim().to_lowercase();
+            match style.as_str() {
+                "bold" => Ok(String::from("1")),
+                "underline" => Ok(String::from("4")),
+                _ => {
+                    if let Some(color) = COLORS.iter().position(|color| *color == style) {
+                        // bright colors, the same as the default red
+                        Ok(format!("{}", 90 + color))
+                    } else if let Ok(code) = style.parse::<u8>() {
+                        Ok(format!("38;5;{code}"))
+                    } else {
+                        Err(format!(
+                            "{style} isn't one of {}, bold, underline or a 256-color code",
+                            COLORS.join(", ")
+                        ))
+                    }
+                }
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("\x1B[{}m", codes.join(";")))
+}
+
+// Parse the comma-separated colors of --palette into their escape sequences,
+// where each color is a name or a 256-color code (e.g.: "red,green,208")
+pub(crate) fn parse_palette(spec: &str) -> Result<Vec<String>, String> {
+    spec.split(',').map(parse_highlight_color).collect()
+}
+
+// Split the list of files by newline, or by NUL if null is true, skipping the empty lines
+pub(crate) fn parse_file_list(list: &[u8], null: bool) -> Vec<String> {
+    let separator = if null { '\0' } else { '\n' };
+    String::from_utf8_lossy(list)
+        .split(separator)
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// The globs of --include or --exclude, where those without a path separator
+// only match the file names, and the others match the whole paths
+#[derive(Default)]
+pub(crate) struct Globs {
+    pub(crate) names: GlobSet,
+    pub(crate) paths: GlobSet,
+}
+
+impl Globs {
+    pub(crate) fn new<'a>(globs: impl IntoIterator<Item = &'a String>) -> Result<Globs, String> {
+        let (mut names, mut paths) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        for glob in globs {
+            let compiled =
+                Glob::new(glob).map_err(|err| format!("{glob} isn't a valid glob: {err}"))?;
+            if glob.contains('/') || glob.contains(std::path::MAIN_SEPARATOR) {
+                paths.add(compiled);
+            } else {
+                names.add(compiled);
+            }
+        }
+        Ok(Globs {
+            names: names.build().map_err(|err| err.to_string())?,
+            paths: paths.build().map_err(|err| err.to_string())?,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    pub(crate) fn is_match(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        path.file_name()
+            .is_some_and(|name| self.names.is_match(name))
+            || self.paths.is_match(path)
+    }
+}
+
+// Parse the delimiter of --format csv in one character, or "tab" (or "\t") for TSV
+pub(crate) fn parse_delimiter(delimiter: &str) -> Result<char, String> {
+    let mut chars = delimiter.chars();
+    match (delimiter, chars.next(), chars.next()) {
+        ("tab" | "\\t", _, _) => Ok('\t'),
+        (_, Some('"' | '\r' | '\n'), None) => Err(String::from(
+            "quotes and line breaks can't be the delimiter",
+        )),
+        (_, Some(delimiter), None) => Ok(delimiter),
+        _ => Err(String::from(
+            "the delimiter must be one character or \"tab\"",
+        )),
+    }
+}
+
+// Return the largest power of two (up to MAX_LINE_WIDTH) as the line width
+// whose hexdump line with the default grouping fits in the columns
+pub(crate) fn auto_line_width(columns: usize) -> usize {
+    // 8 digits of offset, 3 characters and 1 character in two columns for each byte,
+    // 2 spaces of grouping, 3 characters of "  |" and 1 character of "|"
+    let line_len = |width: usize| 8 + 4 * width + if width > 1 { 2 } else { 0 } + 4;
+    (0..=MAX_LINE_WIDTH.ilog2())
+        .rev()
+        .map(|shift| 1 << shift)
+        .find(|width| line_len(*width) <= columns)
+        .unwrap_or(1)
+}
+
+// Parse an offset either in decimal or in hexadecimal prefixed with 0x
+pub(crate) fn parse_offset(offset: &str) -> Result<usize, String> {
+    let offset = offset.trim().to_lowercase();
+    if let Some(hex) = offset.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else {
+        offset.parse::<usize>()
+    }
+    .map_err(|_| format!("{offset} isn't a decimal or hexadecimal (prefixed with 0x) offset"))
+}
+
+// Parse a UUID strictly in the textual form "01234567-89ab-cdef-0123-456789abcdef"
+// into its 16 bytes in big endian
+pub(crate) fn parse_uuid(uuid: &str) -> Result<[u8; 16], String> {
+    let fields = uuid.split('-').collect::<Vec<_>>();
+    let valid = fields.iter().map(|field| field.len()).eq([8, 4, 4, 4, 12])
+        && fields
+            .iter()
+            .all(|field| field.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        return Err(format!(
+            "{uuid} isn't a UUID in format 01234567-89ab-cdef-0123-456789abcdef"
+        ));
+    }
+    let hex = fields.concat();
+    let mut bytes = [0; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    Ok(bytes)
+}
+
+// Parse a Unicode code point in format "U+XXXX" (4 to 6 hexadecimal digits) into its character,
+// where the surrogates and the values beyond U+10FFFF aren't characters
+pub(crate) fn parse_codepoint(codepoint: &str) -> Result<char, String> {
+    let digits = codepoint
+        .strip_prefix("U+")
+        .or(codepoint.strip_prefix("u+"))
+        .filter(|digits| (4..=6).contains(&digits.len()))
+        .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or(format!("{codepoint} isn't a code point in format U+XXXX"))?;
+    let value = u32::from_str_radix(digits, 16).unwrap();
+    char::try_from(value).map_err(|_| format!("{codepoint} isn't a valid Unicode character"))
+}
+
+// Decode the bytes in base64 of the standard or the URL-safe alphabet, which is told by
+// the characters only in either of them, with or without the padding
+pub(crate) fn parse_base64(text: &str) -> Result<Vec<u8>, String> {
+    let alphabet: &Alphabet = if text.contains(['-', '_']) {
+        &URL_SAFE
+    } else {
+        &STANDARD
+    };
+    let config =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    match GeneralPurpose::new(alphabet, config).decode(text.trim()) {
+        Ok(bytes) if bytes.is_empty() => Err(format!("No byte in base64 {text}")),
+        Ok(bytes) => Ok(bytes),
+        Err(err) => Err(format!("{text} isn't in base64: {err}")),
+    }
+}
+
+// Parse a range of bytes in format "START:LEN", where both of them are offsets
+pub(crate) fn parse_range(range: &str) -> Result<(usize, usize), String> {
+    let (start, len) = range
+        .split_once(':')
+        .ok_or(format!("{range} isn't a range in format START:LEN"))?;
+    Ok((parse_offset(start)?, parse_offset(len)?))
+}
+
+// Parse a size in bytes with an optional suffix K, M or G in powers of 1024 (e.g.: "200M")
+pub(crate) fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim().to_uppercase();
+    let shift = match size.chars().last() {
+        Some('K') => 10,
+        Some('M') => 20,
+        Some('G') => 30,
+        _ => 0,
+    };
+    let number = if shift > 0 {
+        &size[..size.len() - 1]
+    } else {
+        &size
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or(format!(
+            "{size} isn't a size in bytes optionally suffixed with K, M or G"
+        ))
+}
+
+// Convert the options in the config file into the arguments put before those in the
+// command line, where the keys are the long options in snake_case (e.g.: no_color = true)
+pub(crate) fn config_args(config: &str, command: &Command) -> Result<Vec<String>, String> {
+    let table = config
+        .parse::<toml::Table>()
+        .map_err(|err| err.to_string())?;
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        if !command
+            .get_arguments()
+            .any(|arg| arg.get_long() == Some(long.as_str()) && !arg.is_hide_set())
+        {
+            return Err(format!("{key} isn't an option"));
+        }
+        // an array gives the option multiple times, e.g.: pattern = ["1f 8b 08", "4d 5a"]
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => args.push(format!("--{long}")),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(value) => args.push(format!("--{long}={value}")),
+                toml::Value::Integer(value) => args.push(format!("--{long}={value}")),
+                toml::Value::Float(value) => args.push(format!("--{long}={value}")),
+                _ => return Err(format!("{key} isn't a boolean, string or number")),
+            }
+        }
+    }
+    Ok(args)
+}
+
+// Build the command line interface, which is also used to generate the shell completions
+pub(crate) fn build_cli() -> Command {
+    Command::new(env!("CARGO_BIN_NAME"))
+        .about("A CLI utility to search arbitrary bytes in files")
+        .version(crate_version!())
+        .arg_required_else_help(true)
+        // the arguments in the command line override those in the config file
+        .args_override_self(true)
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::Count)
+                .help("Suppress all normal output and only set the exit code (0: found, 1: not found)\nafter the first match, given twice to suppress error messages as well"),
+        )
+        .arg(
+            Arg::new("endian")
+                .short('e')
+                .long("endian")
+                .help("Specify the endianness of the bytes in format \"0x088b1f\", --f32, --f64 and the integers,\nor of the words of --word-size bytes in format \"1f 8b 08\"")
+                .value_parser(["big", "little"])
+                .default_value("big"),
+        )
+        .arg(
+            Arg::new("word-size")
+                .long("word-size")
+                .value_parser(["1", "2", "4", "8"])
+                .default_value("1")
+                .help("Swap the bytes of each word of the size in format \"1f 8b 08\" with -e little,\nwhere the number of bytes must be a multiple of it"),
+        )
+        .arg(
+            Arg::new("string-utf16")
+                .long("string-utf16")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the bytes as a text encoded in UTF-16LE (e.g.: \"kernel32.dll\")"),
+        )
+        .arg(
+            Arg::new("string-utf16be")
+                .long("string-utf16be")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["octal", "decimal", "mask"])
+                .help("Search the bytes as a text encoded in UTF-16BE"),
+        )
+        .arg(
+            Arg::new("f32")
+                .long("f32")
+                .value_parser(value_parser!(f32))
+                .allow_negative_numbers(true)
+                .conflicts_with_all(["f64", "string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the 32-bit float value (e.g.: 3.14159) in the endianness of --endian,\nand then all positional arguments are the files to search"),
+        )
+        .arg(
+            Arg::new("f64")
+                .long("f64")
+                .value_parser(value_parser!(f64))
+                .allow_negative_numbers(true)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the 64-bit float value in the endianness of --endian"),
+        )
+        .arg(
+            Arg::new("int8")
+                .long("int8")
+                .allow_hyphen_values(true)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the 8-bit integer in decimal or hexadecimal prefixed with 0x, where a negative\nvalue is in two's complement (e.g.: both of 255 and -1 are ff), and then all positional\narguments are the files to search"),
+        )
+        .arg(
+            Arg::new("int16")
+                .long("int16")
+                .allow_hyphen_values(true)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the 16-bit integer in the endianness of --endian"),
+        )
+        .arg(
+            Arg::new("int32")
+                .long("int32")
+                .allow_hyphen_values(true)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the 32-bit integer in the endianness of --endian"),
+        )
+        .arg(
+            Arg::new("int64")
+                .long("int64")
+                .allow_hyphen_values(true)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the 64-bit integer in the endianness of --endian"),
+        )
+        .arg(
+            Arg::new("uuid")
+                .long("uuid")
+                .value_name("UUID")
+                .value_parser(parse_uuid)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the UUID (or GUID) in format 01234567-89ab-cdef-0123-456789abcdef as stored in\n--uuid-layout, and then all positional arguments are the files to search"),
+        )
+        .arg(
+            Arg::new("codepoint")
+                .long("codepoint")
+                .value_name("U+XXXX")
+                .value_parser(parse_codepoint)
+                .action(ArgAction::Append)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the Unicode character (e.g.: U+00E9) encoded in UTF-8, which can be given\nmultiple times to search the characters in sequence, and then all positional arguments\nare the files to search"),
+        )
+        .arg(
+            Arg::new("struct")
+                .long("struct")
+                .value_name("TEMPLATE")
+                .value_parser(parse_struct)
+                .allow_hyphen_values(true)
+                .conflicts_with_all(["string-utf16", "string-utf16be", "octal", "decimal", "mask"])
+                .help("Search the fields of a struct given as space-separated type:value (e.g.: \"u32_le:0x12345678 u8:0xff u16_be:*\"),\nwhere the types are u8, u16_le, u16_be, u32_le, u32_be, u64_le and u64_be and * is any value,\nand then all positional arguments are the files to search"),
+        )
+        .arg(
+            Arg::new("uuid-layout")
+                .long("uuid-layout")
+                .value_parser(["mixed", "be", "le"])
+                .default_value("mixed")
+                .help("Byte order of the stored --uuid, mixed is the first three fields in little endian\nand the rest in big endian like GPT and Windows, be and le are all bytes in the order"),
+        )
+        .arg(
+            Arg::new("signed")
+                .long("signed")
+                .action(ArgAction::SetTrue)
+                .help("Only accept the values of --int8, --int16, --int32 and --int64 in the range of signed integers"),
+        )
+        .arg(
+            Arg::new("float-epsilon")
+                .long("float-epsilon")
+                .value_parser(value_parser!(f64))
+                .help("Find the floats of --f32 or --f64 within the tolerance of the value rather than the exact bytes"),
+        )
+        .arg(
+            Arg::new("regex")
+                .long("regex")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "string-utf16",
+                    "string-utf16be",
+                    "octal",
+                    "decimal",
+                    "mask",
+                    "f32",
+                    "f64",
+                    "int8",
+                    "int16",
+                    "int32",
+                    "int64",
+                    "uuid",
+                    "codepoint",
+                    "base64-pattern",
+                    "struct",
+                ])
+                .help("Search the bytes as a regex of the regex crate over bytes (e.g.: \"\\x7fELF[\\x01\\x02]\"),\nwhere \\xNN matches the byte NN"),
+        )
+        .arg(
+            Arg::new("unicode")
+                .short('u')
+                .long("unicode")
+                .action(ArgAction::SetTrue)
+                .requires("regex")
+                .help("Enable Unicode in --regex, e.g.: \\p{L} and \\w match the characters encoded in UTF-8,\nwhich only matches valid UTF-8 and may miss the matches in non-UTF-8 bytes"),
+        )
+        .arg(
+            Arg::new("octal")
+                .long("octal")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("decimal")
+                .help("Interpret the bytes as space-separated octal values (e.g.: \"037 213 010\")"),
+        )
+        .arg(
+            Arg::new("decimal")
+                .long("decimal")
+                .action(ArgAction::SetTrue)
+                .help("Interpret the bytes as space-separated decimal values (e.g.: \"31 139 8\")"),
+        )
+        .arg(
+            Arg::new("mask")
+                .short('m')
+                .long("mask")
+                .conflicts_with_all(["octal", "decimal"])
+                .help("Quoted mask bytes in hexadecimal format (e.g.: \"ff ff f0\"), one mask per byte, to match bytes\nwhere (byte & mask) == (value & mask)"),
+        )
+        .arg(
+            Arg::new("context")
+                .short('c')
+                .long("context")
+                .value_parser(value_parser!(u8).range(1..=10))
+                .help("Show 1-10 lines of context bytes when pattern is found in the file"),
+        )
+        .arg(
+            Arg::new("dump")
+                .short('d')
+                .long("dump")
+                .num_args(2)
+                .value_names(["START", "END"])
+                .value_parser(parse_offset)
+                .conflicts_with_all(PATTERN_ARGS)
+                .conflicts_with("validate")
+                .help("Hex-dump the bytes from START until END in decimal or hexadecimal prefixed with 0x\n(e.g.: --dump 0x8000 0x8100) without searching, where all positional arguments are files"),
+        )
+        .arg(
+            Arg::new("diff")
+                .long("diff")
+                .num_args(2)
+                .value_names(["FILE1", "FILE2"])
+                .value_hint(ValueHint::FilePath)
+                .conflicts_with_all(PATTERN_ARGS)
+                .conflicts_with_all(["bytes", "files", "files-from", "dump", "validate"])
+                .help("Compare two files byte by byte without searching, and print the lines where they differ\nwith the different bytes highlighted, < for FILE1 and > for FILE2"),
+        )
+        .arg(
+            Arg::new("before-context")
+                .short('B')
+                .long("before-context")
+                .value_parser(value_parser!(u8))
+                .help("Show 0-255 lines of context bytes before each match, overriding -c"),
+        )
+        .arg(
+            Arg::new("after-context")
+                .short('A')
+                .long("after-context")
+                .value_parser(value_parser!(u8))
+                .help("Show 0-255 lines of context bytes after each match, overriding -c"),
+        )
+        .arg(
+            Arg::new("skip")
+                .long("skip")
+                .value_name("N")
+                .value_parser(value_parser!(usize))
+                .help("Skip the first N matches of each file, which aren't printed or counted"),
+        )
+        .arg(
+            Arg::new("near")
+                .long("near")
+                .value_parser(parse_offset)
+                .help("Only show the match nearest to the offset in decimal or hexadecimal prefixed with 0x"),
+        )
+        .arg(
+            Arg::new("near-pattern")
+                .long("near-pattern")
+                .requires("within")
+                .conflicts_with("then")
+                .help("Only show the matches which have the bytes of another pattern (in the same format\nas the positional bytes) within --within bytes of them"),
+        )
+        .arg(
+            Arg::new("then")
+                .long("then")
+                .value_name("PATTERN")
+                .requires("within")
+                .conflicts_with_all(["reverse-search", "float-epsilon", "pattern-file"])
+                .help("Only show the matches followed by the bytes of another pattern (in the same format as the\npositional bytes) starting within --within bytes after them, or before them if negative"),
+        )
+        .arg(
+            Arg::new("within")
+                .long("within")
+                .value_parser(value_parser!(i64))
+                .allow_negative_numbers(true)
+                .help("Maximum distance in bytes between the offsets of the matches of --near-pattern,\nor from the offsets of the matches to the matches of --then"),
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .help(format!("Print one line per match in the format instead of the hexdump, with tokens:\n{{file}}, {{offset}} (in --offset-base), {{offset_dec}}, {{offset_hex}}, {{length}}, {{hex_bytes}}, {{ascii}}\nand {{entropy}} in bits per byte (e.g.: \"{DEFAULT_OUTPUT_FORMAT}\")")),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_parser(["hexdump", "csv", "grep", "xxd"])
+                .default_value("hexdump")
+                .conflicts_with_all(["output-format", "only-matching", "dump-match"])
+                .help("Output format, csv prints a header row and one row per match with columns:\nfile, offset_dec, offset_hex, length and matched_bytes_hex (and context_hex with context,\nand entropy of the matched bytes, or their context if any, with --show-entropy),\nand grep prints one line per match like \"vmlinuz:0x0061bd72: 1f 8b 08 |...|\",\nand xxd prints the hexdump in the default layout of xxd"),
+        )
+        .arg(
+            Arg::new("delimiter")
+                .long("delimiter")
+                .value_name("CHAR")
+                .value_parser(parse_delimiter)
+                .default_value(",")
+                .help("Field delimiter of --format csv, e.g.: \"tab\" for TSV"),
+        )
+        .arg(
+            Arg::new("no-header")
+                .long("no-header")
+                .action(ArgAction::SetTrue)
+                .help("Don't print the header row of --format csv"),
+        )
+        .arg(
+            Arg::new("only-matching")
+                .short('o')
+                .long("only-matching")
+                .action(ArgAction::SetTrue)
+                .help(format!("Print only the matched bytes of each match in the format \"{ONLY_MATCHING_FORMAT}\"")),
+        )
+        .arg(
+            Arg::new("dump-match")
+                .long("dump-match")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["output-format", "only-matching"])
+                .help(format!("Print only the matched bytes of each match in one row in the format \"{DUMP_MATCH_FORMAT}\"")),
+        )
+        .arg(
+            Arg::new("line-offset-only")
+                .long("line-offset-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "format",
+                    "output-format",
+                    "only-matching",
+                    "dump-match",
+                    "emit",
+                    "context",
+                    "before-context",
+                    "after-context",
+                    "before-bytes",
+                    "after-bytes",
+                ])
+                .help(format!("Print only the offset of the line having each match, aligned to --width,\nin the format \"{LINE_OFFSET_ONLY_FORMAT}\"")),
+        )
+        .arg(
+            Arg::new("emit")
+                .long("emit")
+                .value_parser(["c", "rust", "python"])
+                .conflicts_with_all(["format", "output-format", "only-matching", "dump-match"])
+                .help("Print the matched bytes of each match, and the bytes of --after-bytes after it,\nas a C array, a Rust byte string or a Python bytes literal named after the offset"),
+        )
+        .arg(
+            Arg::new("null")
+                .short('z')
+                .visible_short_alias('0')
+                .long("null")
+                .action(ArgAction::SetTrue)
+                .help("Terminate each offset line, or each line in output formats, with NUL instead of newline"),
+        )
+        .arg(
+            Arg::new("compact")
+                .long("compact")
+                .action(ArgAction::SetTrue)
+                .help("Don't print the blank line between the matches without context lines or bytes, like grep,\nwhile --context-separator is still printed if it's given"),
+        )
+        .arg(
+            Arg::new("context-separator")
+                .long("context-separator")
+                .default_value("")
+                .hide_default_value(true)
+                .help("String printed between the non-adjacent blocks of matches and their context,\nwhich are merged into one block when overlapping [default: blank line]"),
+        )
+        .arg(
+            Arg::new("relative-offsets")
+                .long("relative-offsets")
+                .action(ArgAction::SetTrue)
+                .help("Show the offset of each match relative to the previous match (or 0 for the first one)"),
+        )
+        .arg(
+            Arg::new("show-gaps")
+                .long("show-gaps")
+                .action(ArgAction::SetTrue)
+                .help("Show the gap from the previous match for each match,\nand the minimum, maximum and most common gaps of each file"),
+        )
+        .arg(
+            Arg::new("byte-frequency")
+                .long("byte-frequency")
+                .action(ArgAction::SetTrue)
+                .help("Print the counts of the byte values in descending order after the hexdump of each match\nand its context, e.g.: to tell encrypted or compressed bytes"),
+        )
+        .arg(
+            Arg::new("show-entropy")
+                .long("show-entropy")
+                .action(ArgAction::SetTrue)
+                .help("Print the Shannon entropy in bits per byte of the bytes in the hexdump of each match\nand its context after it, where high entropy suggests encrypted or compressed bytes"),
+        )
+        .arg(
+            Arg::new("before-bytes")
+                .long("before-bytes")
+                .value_parser(value_parser!(usize))
+                .conflicts_with_all(["context", "before-context", "after-context"])
+                .help("Show exactly N bytes of context before each match instead of whole lines"),
+        )
+        .arg(
+            Arg::new("after-bytes")
+                .long("after-bytes")
+                .value_parser(value_parser!(usize))
+                .conflicts_with_all(["context", "before-context", "after-context"])
+                .help("Show exactly N bytes of context after each match instead of whole lines"),
+        )
+        .arg(
+            Arg::new("hex-only")
+                .long("hex-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("ascii-only")
+                .help("Print only the column of hexadecimal bytes in the hexdump"),
+        )
+        .arg(
+            Arg::new("ascii-only")
+                .long("ascii-only")
+                .action(ArgAction::SetTrue)
+                .help("Print only the column of characters in the hexdump"),
+        )
+        .arg(
+            Arg::new("utf8-ascii")
+                .long("utf8-ascii")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("hex-only")
+                .help("Decode the column of characters as UTF-8, where a character is shown at its first byte\nand the invalid sequences and the control characters are still shown as '.'"),
+        )
+        .arg(
+            Arg::new("line-numbers")
+                .long("line-numbers")
+                .action(ArgAction::SetTrue)
+                .help("Print the 0-indexed number of each line of the hexdump from the start of the file\n(the offset divided by the line width) between the offset and the bytes"),
+        )
+        .arg(
+            Arg::new("align")
+                .long("align")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Only find the matches at offsets aligned to N bytes"),
+        )
+        .arg(
+            Arg::new("align-offset")
+                .long("align-offset")
+                .value_parser(value_parser!(usize))
+                .requires("align")
+                .help("Phase of the alignment, i.e.: only find the matches where (offset - M) % N == 0"),
+        )
+        .arg(
+            Arg::new("print0")
+                .long("print0")
+                .action(ArgAction::SetTrue)
+                .help("Terminate each record with NUL instead of newline in the machine output,\ni.e.: --only-matching, --dump-match, --output-format or --format csv"),
+        )
+        .arg(
+            Arg::new("reverse-search")
+                .short('R')
+                .long("reverse-search")
+                .action(ArgAction::SetTrue)
+                .help("Search from the end of file toward the beginning, which is faster to find\nthe matches near the end with -q, while the matches are still shown in ascending order"),
+        )
+        .arg(
+            Arg::new("anchor-start")
+                .long("anchor-start")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["reverse-search", "float-epsilon", "follow"])
+                .help("Only match the pattern at offset 0, e.g.: for the magic of a file,\nwhich is the same as prepending \\A to the regex"),
+        )
+        .arg(
+            Arg::new("anchor-end")
+                .long("anchor-end")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["reverse-search", "float-epsilon", "follow"])
+                .help("Only match the pattern ending at the last byte of file, e.g.: for a trailer,\nwhich is the same as appending \\z to the regex"),
+        )
+        .arg(
+            Arg::new("decompress")
+                .short('Z')
+                .long("decompress")
+                .action(ArgAction::SetTrue)
+                .help("Search the decompressed bytes of gzip and xz files, which are detected by the magic bytes,\nand show the offsets in the decompressed bytes, while other files are searched as is\n[default: only for .gz files]"),
+        )
+        .arg(
+            Arg::new("replace")
+                .long("replace")
+                .conflicts_with("quiet")
+                .help("Overwrite each match with the bytes in format \"1f 8b 08\" or \"0x088b1f\",\nwhich must have the same length as the match, after backing up the file to <file>.bak"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .requires("replace")
+                .help("Only show what --replace would change without modifying the files"),
+        )
+        .arg(
+            Arg::new("no-backup")
+                .long("no-backup")
+                .action(ArgAction::SetTrue)
+                .requires("replace")
+                .help("Don't back up the files to <file>.bak before --replace modifies them"),
+        )
+        .arg(
+            Arg::new("extract")
+                .long("extract")
+                .value_name("LENGTH")
+                .value_parser(|length: &str| match length {
+                    "auto" => Ok(ExtractLength::Auto),
+                    "match" => Ok(ExtractLength::Match),
+                    _ => match parse_size(length)? {
+                        0 => Err(String::from("The length to extract must be positive.")),
+                        length => Ok(ExtractLength::Bytes(length)),
+                    },
+                })
+                .conflicts_with_all(["replace", "quiet"])
+                .help("Write LENGTH bytes with an optional suffix K, M or G from each match into\n<DIR>/<file>_<offset>.bin, or the bytes till the next match or EOF with auto,\nor the matched bytes with match, where the raw bytes are written to stdout without --extract-dir"),
+        )
+        .arg(
+            Arg::new("extract-dir")
+                .long("extract-dir")
+                .value_name("DIR")
+                .value_hint(ValueHint::DirPath)
+                .requires("extract")
+                .help("The directory to write the bytes of --extract into, which is created if needed"),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Overwrite the existing files of --extract, or the existing backups of --replace"),
+        )
+        .arg(
+            Arg::new("max-extract")
+                .long("max-extract")
+                .value_parser(value_parser!(usize))
+                .default_value("1000")
+                .help("Stop extracting after writing so many files of --extract, 0 means no limit"),
+        )
+        .arg(
+            Arg::new("extract-separator")
+                .long("extract-separator")
+                .value_name("HEX_BYTES")
+                .value_parser(|bytes: &str| {
+                    parse_spaced_hex(bytes)?
+                        .into_iter()
+                        .collect::<Option<Vec<u8>>>()
+                        .ok_or(String::from("?? isn't a byte of the separator."))
+                })
+                .requires("extract")
+                .conflicts_with("extract-dir")
+                .help("The bytes (e.g.: \"0a\") written between the bytes of --extract written to stdout,\nwhich are concatenated by default"),
+        )
+        .arg(
+            Arg::new("elf-section")
+                .long("elf-section")
+                .value_name("NAME")
+                .help("Only search the bytes of the named section of ELF files (e.g.: .rodata),\nand show the offset in the section along with the offset of each match"),
+        )
+        .arg(
+            Arg::new("follow")
+                .short('F')
+                .long("follow")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "quiet",
+                    "reverse-search",
+                    "near",
+                    "near-pattern",
+                    "pattern-file",
+                    "float-epsilon",
+                    "align",
+                    "replace",
+                    "extract",
+                    "elf-section",
+                    "decompress",
+                    "dump",
+                    "diff",
+                    "validate",
+                    "skip",
+                ])
+                .help("Keep searching the bytes appended to the file after reaching the end of it,\nlike tail -F, until Ctrl-C is pressed"),
+        )
+        .arg(
+            Arg::new("poll-interval")
+                .long("poll-interval")
+                .value_name("MS")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("500")
+                .help("Milliseconds to wait before checking the file of --follow again"),
+        )
+        .arg(
+            Arg::new("max-filesize")
+                .long("max-filesize")
+                .visible_alias("max-file-size")
+                .value_parser(parse_size)
+                .help("Skip the files larger than the size with an optional suffix K, M or G (e.g.: 200M),\n0 means no limit"),
+        )
+        .arg(
+            Arg::new("no-decompress")
+                .long("no-decompress")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("decompress")
+                .help("Search the compressed bytes of .gz files, which are decompressed by default"),
+        )
+        .arg(
+            Arg::new("buffer-size")
+                .long("buffer-size")
+                .value_parser(parse_size)
+                .help("Size of the buffer to read the files with an optional suffix K, M or G (e.g.: 4M),\nwhich must not be less than the bytes of the pattern [default: 1M]"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::Count)
+                .help("Print the regex built from the bytes, and the bytes scanned, the time, the matches,\nthe engine and the buffer size of each file to stderr, given twice to also trace\neach chunk read and each rewind around the chunk boundaries"),
+        )
+        .arg(
+            Arg::new("file-footer")
+                .long("file-footer")
+                .action(ArgAction::SetTrue)
+                .help("Print a footer with the matches and the size of each file after its hexdump to stderr,\ne.g.: \"--- 3 match(es) found in firmware.bin (8388608 bytes) ---\""),
+        )
+        .arg(
+            Arg::new("stats")
+                .long("stats")
+                .action(ArgAction::SetTrue)
+                .help("Print the files searched, matched and failed, the total matches,\nthe bytes scanned, the elapsed time and the throughput to stderr at the end"),
+        )
+        .arg(
+            Arg::new("validate")
+                .long("validate")
+                .action(ArgAction::SetTrue)
+                .help("Only check whether the bytes make a valid regex without searching any file,\nand exit with 0 if it's valid or 2 if the regex engine rejects it"),
+        )
+        .arg(
+            Arg::new("width")
+                .short('w')
+                .long("width")
+                .value_parser(value_parser!(u8).range(1..=MAX_LINE_WIDTH as i64))
+                .help("Line width of 1-64 bytes when printing the search result [default: the largest power of two\nfitting in the terminal, or 16 if stdout isn't a terminal]"),
+        )
+        .arg(
+            Arg::new("groupsize")
+                .short('g')
+                .long("groupsize")
+                .value_parser(value_parser!(usize))
+                .help("Group the hexadecimal bytes every N bytes, 0 means no grouping [default: half of the line width]"),
+        )
+        .arg(
+            Arg::new("base-address")
+                .long("base-address")
+                .value_name("ADDR")
+                .value_parser(parse_offset)
+                .help("Add the address in decimal or hexadecimal prefixed with 0x (e.g.: 0x08000000)\nto all displayed offsets, e.g.: where a firmware or a memory dump is loaded"),
+        )
+        .arg(
+            Arg::new("offset-base")
+                .long("offset-base")
+                .value_parser(["hex", "dec", "oct"])
+                .default_value("hex")
+                .help("Base of the offsets in the hexdump"),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto")
+                .help("When to use colors, auto means only when stdout is a terminal supporting them"),
+        )
+        .arg(
+            Arg::new("export-offsets")
+                .long("export-offsets")
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .help("Also write the offset of each match to a file, one per line in the base of --offset-base\n(e.g.: 0x61bd72), while the output is printed as usual"),
+        )
+        .arg(
+            Arg::new("output-file")
+                .long("output-file")
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .help("Write the output to a file instead of stdout, while the errors are still printed to stderr"),
+        )
+        .arg(
+            Arg::new("append")
+                .long("append")
+                .action(ArgAction::SetTrue)
+                .requires("output-file")
+                .help("Append the output to --output-file instead of truncating it"),
+        )
+        .arg(
+            Arg::new("pager")
+                .long("pager")
+                .value_name("CMD")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("")
+                .help("Page the output through CMD when stdout is a terminal, or through $PAGER\nor less -R if CMD isn't given, while the errors still go to the terminal"),
+        )
+        .arg(
+            Arg::new("no-pager")
+                .long("no-pager")
+                .action(ArgAction::SetTrue)
+                .overrides_with("pager")
+                .help("Don't page the output, e.g.: to override --pager in the config file"),
+        )
+        .arg(
+            Arg::new("no-config")
+                .long("no-config")
+                .action(ArgAction::SetTrue)
+                .help("Don't read the options in $XDG_CONFIG_HOME/hexsearch/config.toml\n(or ~/.config/hexsearch/config.toml)"),
+        )
+        .arg(
+            Arg::new("no-color")
+                .long("no-color")
+                .action(ArgAction::SetTrue)
+                .overrides_with("color")
+                .help("Same as --color never"),
+        )
+        .arg(
+            Arg::new("highlight")
+                .long("highlight")
+                .value_parser(["color", "bold", "underline", "invert"])
+                .default_value("color")
+                .help("Highlight style of the matched bytes, where color is given by --highlight-color"),
+        )
+        .arg(
+            Arg::new("ascii-highlight")
+                .long("ascii-highlight")
+                .value_name("CHAR")
+                .value_parser(value_parser!(char))
+                .help("Also mark the highlighted bytes with CHAR around them in the column of characters,\nand with > and < in the column of hexadecimal bytes [default: * with --no-color]"),
+        )
+        .arg(
+            Arg::new("highlight-color")
+                .long("highlight-color")
+                .value_parser(parse_highlight_color)
+                .default_value("red")
+                .help("Highlight style of the matched bytes in comma-separated color names,\nbold, underline or 256-color codes (e.g.: \"bold,yellow\" or \"208\")"),
+        )
+        .arg(
+            Arg::new("palette")
+                .long("palette")
+                .value_name("COLORS")
+                .value_parser(parse_palette)
+                .help("Highlight the matches of each pattern in the comma-separated colors cycling\nfrom the first pattern, instead of --highlight-color and then red, green,\nyellow, blue, magenta and cyan (e.g.: \"red,green,208\")"),
+        )
+        .arg(
+            Arg::new("highlight-all")
+                .long("highlight-all")
+                .visible_alias("highlight-all-occurrences")
+                .action(ArgAction::SetTrue)
+                .help("Also highlight the occurrences of the patterns in the context lines which aren't\nreported as matches, e.g.: overlapping, skipped or unaligned ones"),
+        )
+        .arg(
+            Arg::new("pattern")
+                .short('p')
+                .long("pattern")
+                .action(ArgAction::Append)
+                .help("Search the bytes in the same format as the positional bytes, which can be given multiple\ntimes to find any of them, and then all positional arguments are the files to search"),
+        )
+        .arg(
+            Arg::new("needle-file")
+                .long("needle-file")
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .help("Search the bytes of a file verbatim (e.g.: a certificate in a firmware image),\nand then all positional arguments are the files to search"),
+        )
+        .arg(
+            Arg::new("base64-pattern")
+                .long("base64-pattern")
+                .value_name("BASE64")
+                .value_parser(parse_base64)
+                .help("Search the bytes encoded in base64 of the standard or the URL-safe alphabet,\nwith or without the padding, and then all positional arguments are the files to search"),
+        )
+        .arg(
+            Arg::new("needle-range")
+                .long("needle-range")
+                .value_name("START:LEN")
+                .value_parser(parse_range)
+                .requires("needle-file")
+                .help("Only search LEN bytes from START of --needle-file, both in decimal or hexadecimal\nprefixed with 0x (e.g.: 0x100:64)"),
+        )
+        .arg(
+            Arg::new("label")
+                .long("label")
+                .value_name("NAME")
+                .action(ArgAction::Append)
+                .requires("pattern")
+                .help("Name the --pattern right before it, which is shown as [NAME] after the offset of\neach match, while the patterns without --label are shown as their bytes"),
+        )
+        .arg(
+            Arg::new("pattern-file")
+                .short('f')
+                .long("pattern-file")
+                .value_hint(ValueHint::FilePath)
+                .help("Read the patterns from a file, one per line in the same format as the positional bytes,\nskipping empty lines and comments starting with #, and search each of them separately"),
+        )
+        .arg(
+            Arg::new("or")
+                .long("or")
+                .action(ArgAction::SetTrue)
+                .requires("pattern-file")
+                .help("Search the patterns of --pattern-file in one pass to find any of them like -p,\nwhere the first one wins if more than one pattern match at the same offset"),
+        )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("reverse-search")
+                .help("Only match the files containing every one of the patterns, searched in one pass,\nand print the first match of each pattern rather than all matches"),
+        )
+        .arg(
+            Arg::new("bytes")
+                .help("Quoted bytes in hexadecimal format either without 0x (e.g.: \"1f 8b 08\")\nor with 0x in one word and respect --endian argument (e.g.: -e little 0x088b1f)")
+                .required_unless_present_any(PATTERN_ARGS.iter().chain(&["dump", "diff"])),
+        )
+        .arg(
+            Arg::new("files-from")
+                .short('T')
+                .long("files-from")
+                .value_hint(ValueHint::FilePath)
+                .help("Read the files to search from a file (or - for stdin), one per line,\nor separated by NUL with --null"),
+        )
+        .arg(
+            Arg::new("include")
+                .long("include")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("Only search the files matching the glob (e.g.: \"*.bin\"), which can be given multiple times,\nwhere a glob without / matches the file name and the others match the whole path"),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .help("Skip the files matching the glob (e.g.: \"*.map\") in the same way as --include,\nwhich takes precedence over --include"),
+        )
+        .arg(
+            Arg::new("files")
+                .help("files to search")
+                .value_hint(ValueHint::FilePath)
+                .required_unless_present_any(
+                    ["files-from", "validate", "dump", "diff"].iter().chain(&PATTERN_ARGS),
+                )
+                .num_args(1..),
+        )
+        .arg(
+            Arg::new("generate-completions")
+                .long("generate-completions")
+                .alias("generate-completion")
+                .value_name("SHELL")
+                .value_parser(value_parser!(Shell))
+                .exclusive(true)
+                .hide(true)
+                .help("Print the completion script for the shell"),
+        )
+}
+
+// Parse the command line after the options in the config file,
+// i.e.: $XDG_CONFIG_HOME/hexsearch/config.toml or ~/.config/hexsearch/config.toml
+pub(crate) fn get_matches() -> ArgMatches {
+    let mut args = env::args_os().collect::<Vec<_>>();
+    // the config file is skipped with --no-config or without any argument to show the help
+    let no_config = args
+        .iter()
+        .skip(1)
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == "--no-config");
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    if let (false, true, Some(config_dir)) = (no_config, args.len() > 1, config_dir) {
+        let path = config_dir.join("hexsearch").join("config.toml");
+        // it's fine not to have the config file at all
+        if let Ok(config) = fs::read_to_string(&path) {
+            match config_args(&config, &build_cli()) {
+                Ok(config_args) => {
+                    args.splice(1..1, config_args.into_iter().map(OsString::from));
+                }
+                Err(err) => {
+                    eprint_line(&format!("Invalid config file {}: {err}", path.display()));
+                    exit(-1);
+                }
+            }
+        }
+    }
+    build_cli().get_matches_from(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_line_width() {
+        assert_eq!(auto_line_width(80), 16);
+        assert_eq!(auto_line_width(200), 32);
+        assert_eq!(auto_line_width(400), 64);
+        assert_eq!(auto_line_width(10), 1);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4k"), Ok(4 * 1024));
+        assert_eq!(parse_size("200M"), Ok(200 * 1024 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1024 * 1024 * 1024));
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_globs() {
+        let globs = |globs: &[&str]| {
+            Globs::new(
+                &globs
+                    .iter()
+                    .map(|glob| glob.to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap()
+        };
+        assert!(globs(&[]).is_empty());
+        assert!(globs(&["*.bin"]).is_match("firmware/boot.bin"));
+        assert!(!globs(&["*.bin"]).is_match("boot.bin.map"));
+        // a glob with a path separator matches the whole path
+        assert!(globs(&["firmware/*.bin"]).is_match("firmware/boot.bin"));
+        assert!(!globs(&["firmware/*.bin"]).is_match("backup/boot.bin"));
+        assert!(Globs::new(&[String::from("[.bin")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("4096"), Ok(4096));
+        assert_eq!(parse_offset("0x1F0000"), Ok(0x1f0000));
+        assert!(parse_offset("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_parse_uuid() {
+        assert_eq!(
+            parse_uuid("01234567-89AB-cdef-0123-456789abcdef"),
+            Ok([
+                0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab,
+                0xcd, 0xef
+            ])
+        );
+        assert!(parse_uuid("0123456789abcdef0123456789abcdef").is_err());
+        assert!(parse_uuid("{01234567-89ab-cdef-0123-456789abcdef}").is_err());
+        assert!(parse_uuid("0123456-789ab-cdef-0123-456789abcdef").is_err());
+        assert!(parse_uuid("01234567-89ab-cdef-0123-456789abcdeg").is_err());
+        assert!(parse_uuid("+1234567-89ab-cdef-0123-456789abcdef").is_err());
+    }
+
+    #[test]
+    fn test_parse_codepoint() {
+        assert_eq!(parse_codepoint("U+00E9"), Ok('\u{e9}'));
+        assert_eq!(parse_codepoint("u+1f600"), Ok('\u{1f600}'));
+        assert_eq!(parse_codepoint("U+10FFFF"), Ok('\u{10ffff}'));
+        assert!(parse_codepoint("U+D800").is_err());
+        assert!(parse_codepoint("U+110000").is_err());
+        assert!(parse_codepoint("U+E9").is_err());
+        assert!(parse_codepoint("U++0E9").is_err());
+        assert!(parse_codepoint("00E9").is_err());
+    }
+
+    #[test]
+    fn test_parse_base64() {
+        assert_eq!(parse_base64("H4sI"), Ok(vec![0x1f, 0x8b, 0x08]));
+        // the padding is optional
+        assert_eq!(parse_base64("+/8="), Ok(vec![0xfb, 0xff]));
+        assert_eq!(parse_base64("+/8"), Ok(vec![0xfb, 0xff]));
+        assert_eq!(parse_base64("-_8"), Ok(vec![0xfb, 0xff]));
+        assert!(parse_base64("+_8").is_err());
+        assert!(parse_base64("H4s!").is_err());
+        assert!(parse_base64("").is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("0x100:64"), Ok((0x100, 64)));
+        assert!(parse_range("256").is_err());
+        assert!(parse_range("256:x").is_err());
+    }
+
+    #[test]
+    fn test_config_args() {
+        let command = build_cli();
+        assert_eq!(
+            config_args(
+                "width = 32\nno_color = true\nstats = false\npattern = [\"1f 8b\", \"4d 5a\"]",
+                &command
+            ),
+            Ok(vec![
+                String::from("--no-color"),
+                String::from("--pattern=1f 8b"),
+                String::from("--pattern=4d 5a"),
+                String::from("--width=32"),
+            ])
+        );
+        assert!(config_args("files = \"vmlinuz\"", &command).is_err());
+        assert!(config_args("width = {}", &command).is_err());
+        assert!(config_args("width = ", &command).is_err());
+    }
+
+    #[test]
+    fn test_parse_highlight_color() {
+        assert_eq!(parse_highlight_color("red"), Ok(String::from("\x1B[91m")));
+        assert_eq!(
+            parse_highlight_color("bold,Yellow"),
+            Ok(String::from("\x1B[1;93m"))
+        );
+        assert_eq!(
+            parse_highlight_color("underline,208"),
+            Ok(String::from("\x1B[4;38;5;208m"))
+        );
+        assert!(parse_highlight_color("purple").is_err());
+    }
+
+    #[test]
+    fn test_parse_palette() {
+        assert_eq!(
+            parse_palette("green,208"),
+            Ok(vec![
+                String::from("\x1B[92m"),
+                String::from("\x1B[38;5;208m")
+            ])
+        );
+        assert!(parse_palette("red,,green").is_err());
+    }
+
+    #[test]
+    fn test_parse_file_list() {
+        assert_eq!(parse_file_list(b"a b\n\nc\n", false), vec!["a b", "c"]);
+        assert_eq!(parse_file_list(b"a\nb\0c\0", true), vec!["a\nb", "c"]);
+    }
+}
@@ -1,325 +1,353 @@
-use clap::{crate_version, value_parser, Arg, Command};
-use regex::bytes::RegexBuilder;
+use clap::ArgMatches;
+use clap_complete::{generate, Shell};
+use enable_ansi_support::enable_ansi_support;
+#[cfg(not(feature = "async"))]
+use std::collections::HashMap;
 use std::{
-    fs::File,
-    io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom},
-    ops::Range,
+    cell::{Cell, RefCell},
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, IsTerminal, Read},
     process::exit,
+    time::Instant,
 };
+use terminal_size::{terminal_size, Width};
+use tracing::debug;
+use tracing_subscriber::EnvFilter;
 
-const G_VT_DEFAULT: &str = "\x1B[0m";
-const G_VT_BOLD: &str = "\x1B[1m";
-const G_VT_RED: &str = "\x1B[91m";
+mod actions;
+mod cli;
+mod pattern;
+mod printer;
+mod report;
+mod search;
 
-fn search_regex(file: &File, pattern: &str) -> Result<Vec<usize>, io::Error> {
-    let mut buff = BufReader::new(file);
-    let mut bytes = vec![0; 1024];
-    // Disable Unicode (\u flag) to search arbitrary (non-UTF-8) bytes
-    let re = if let Ok(re) = RegexBuilder::new(pattern).unicode(false).build() {
-        re
-    } else {
-        return Err(io::Error::from(ErrorKind::InvalidInput));
-    };
+use crate::{
+    actions::{diff, dump},
+    cli::{auto_line_width, build_cli, explicit_patterns, get_matches, parse_file_list, Globs},
+    pattern::Patterns,
+    printer::{
+        HighlightStyle, Layout, OffsetBase, Printer, Style, Writer, G_VT_BOLD, G_VT_DEFAULT,
+        G_VT_GREEN, G_VT_MAGENTA,
+    },
+    report::{Reporter, Totals},
+    search::DEFAULT_BUFFER_SIZE,
+};
 
-    buff.seek(SeekFrom::Start(0))?;
-    let mut offsets = Vec::new();
-    loop {
-        match buff.read(&mut bytes) {
-            Ok(read) => {
-                if read == 0 {
-                    break;
-                }
-                // Note: pattern.len() is the length of the string, not bytes
-                if read < pattern.len() {
-                    // if remaining bytes is shorter than a pattern,
-                    // search again the last length of pattern
-                    buff.seek(SeekFrom::End(pattern.len() as i64))?;
-                    continue;
-                }
-                // find all non-overlapping matches
-                for m in re.find_iter(&bytes[..read]) {
-                    offsets.extend_from_slice(&[
-                        buff.stream_position().unwrap() as usize - (read - m.start())
-                    ]);
-                }
-                // overlap the search around the chunk boundaries
-                // in case the pattern locates across the boundary
-                buff.seek(SeekFrom::Current(1 - pattern.len() as i64))?;
-            }
-            Err(err) => {
-                return Err(err);
-            }
+// Whether the escape sequences of the colors are printed
+fn use_color(matches: &ArgMatches, terminal: bool) -> bool {
+    // format argument has default value so it's safe to unwrap
+    let csv = matches.get_one::<String>("format").unwrap() == "csv";
+    match matches.get_one::<String>("color").unwrap().as_str() {
+        // nothing but the CSV rows is written to stdout
+        _ if csv => false,
+        _ if matches.get_flag("no-color") => false,
+        "always" => {
+            // still try to make the Windows consoles interpret the escape sequences
+            let _ = enable_ansi_support();
+            true
         }
-    }
-
-    if offsets.is_empty() {
-        Err(io::Error::from(ErrorKind::NotFound))
-    } else {
-        Ok(offsets)
+        "never" => false,
+        // the escape sequences are printed as they are on the Windows consoles
+        // without virtual terminal processing, which is always supported on Unix
+        _auto => terminal && enable_ansi_support().is_ok(),
     }
 }
 
-fn read_and_print_one_line(
-    file: &mut File,
+// Build the printer writing to stdout or --output-file
+fn build_printer(
+    matches: &ArgMatches,
     line_width: usize,
-    line_offset: usize,
-    range: Range<usize>,
-) {
-    let mut bytes = vec![0; line_width];
-    if file.seek(SeekFrom::Start(line_offset as u64)).is_err() {
-        return;
-    }
-    let read = file.read(&mut bytes[..]).unwrap_or_default();
-    if read == 0 {
-        return;
-    }
-
-    // header
-    print!("{line_offset:08x}");
-
-    // hexadecimal bytes
-    for (i, byte) in bytes.iter().enumerate() {
-        if line_width != 1 && i % (line_width / 2) == 0 {
-            print!(" ");
+    color: bool,
+    highlight: HighlightStyle,
+) -> Printer {
+    let xxd = matches.get_one::<String>("format").unwrap() == "xxd";
+    let style = if color {
+        Style {
+            bold: String::from(G_VT_BOLD),
+            // highlight-color argument has default value so it's safe to unwrap
+            highlight: highlight.escape(matches.get_one::<String>("highlight-color").unwrap()),
+            file: String::from(G_VT_MAGENTA),
+            offset: String::from(G_VT_GREEN),
+            reset: String::from(G_VT_DEFAULT),
         }
-        if range.contains(&i) {
-            print!("{G_VT_RED}");
+    } else {
+        Style {
+            bold: String::new(),
+            highlight: String::new(),
+            file: String::new(),
+            offset: String::new(),
+            reset: String::new(),
         }
-        if i < read {
-            print!(" {byte:02x}");
-        } else {
-            // print spaces as place holder
-            print!("   ");
+    };
+    let mut printer = Printer {
+        offset_base: match matches.get_one::<String>("offset-base").unwrap().as_str() {
+            "dec" => OffsetBase::Dec,
+            "oct" => OffsetBase::Oct,
+            _hex => OffsetBase::Hex,
+        },
+        offset_width: Cell::new(8),
+        base_address: matches
+            .get_one::<usize>("base-address")
+            .copied()
+            .unwrap_or_default(),
+        style,
+        quiet: matches.get_count("quiet"),
+        null: matches.get_flag("null") || matches.get_flag("print0"),
+        layout: if xxd { Layout::Xxd } else { Layout::Canonical },
+        hex_only: matches.get_flag("hex-only"),
+        ascii_only: matches.get_flag("ascii-only"),
+        line_numbers: matches.get_flag("line-numbers"),
+        utf8: matches.get_flag("utf8-ascii"),
+        // the highlighted bytes can't be told without colors unless they're marked,
+        // while the output in a pipe stays unmarked as before
+        marker: matches
+            .get_one::<char>("ascii-highlight")
+            .copied()
+            .or((matches.get_flag("no-color")
+                || matches.get_one::<String>("color").unwrap() == "never")
+                .then_some('*')),
+        // xxd groups every 2 bytes by default, and a line of 1 byte is still one group
+        // so that the bytes are spaced from the offset like the wider lines
+        group_size: matches
+            .get_one::<usize>("groupsize")
+            .copied()
+            .unwrap_or(if xxd { 2 } else { (line_width / 2).max(1) }),
+        ..Default::default()
+    };
+    // --output-file takes the output instead of stdout, truncated unless --append is given
+    if let Some(output_file) = matches.get_one::<String>("output-file") {
+        let append = matches.get_flag("append");
+        match OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(output_file)
+        {
+            Ok(file) => printer.out = RefCell::new(Writer::File(BufWriter::new(file))),
+            Err(err) => {
+                printer.error(&format!("Failed to open output file {output_file}: {err}"));
+                exit(-1);
+            }
         }
-        print!("{G_VT_DEFAULT}");
     }
-
-    // chracters
-    print!("  |");
-    for (i, byte) in bytes.iter().enumerate() {
-        if range.contains(&i) {
-            print!("{G_VT_RED}");
-        }
-        if i < read {
-            if byte.is_ascii() && !byte.is_ascii_control() {
-                print!("{}", *byte as char);
-            } else {
-                print!(".");
+    if let Some(path) = matches.get_one::<String>("export-offsets") {
+        match File::create(path) {
+            Ok(file) => printer.export = Some((path.clone(), file)),
+            Err(err) => {
+                printer.error(&format!("Failed to create file {path}: {err}"));
+                exit(-1);
             }
-        } else {
-            print!(" ");
         }
-        print!("{G_VT_DEFAULT}");
     }
-    println!("|");
+    printer
 }
 
-fn main() {
-    let matches = Command::new(env!("CARGO_BIN_NAME"))
-        .about("A CLI utility to search arbitrary bytes in files")
-        .version(crate_version!())
-        .arg_required_else_help(true)
-        .arg(
-            Arg::new("endian")
-                .short('e')
-                .long("endian")
-                .help("Specify the endianness of the bytes")
-                .value_parser(["big", "little"])
-                .default_value("big"),
-        )
-        .arg(
-            Arg::new("context")
-                .short('c')
-                .long("context")
-                .value_parser(value_parser!(u8).range(1..=10))
-                .help("Show 1-10 lines of context bytes when pattern is found in the file"),
-        )
-        .arg(
-            Arg::new("width")
-                .short('w')
-                .long("width")
-                .value_parser(value_parser!(u8).range(1..))
-                .default_value("16")
-                .help("Line width when printing the search result"),
-        )
-        .arg(
-            Arg::new("bytes")
-                .help("Quoted bytes in hexadecimal format either without 0x (e.g.: \"1f 8b 08\")\nor with 0x in one word and respect --endian argument (e.g.: -e little 0x088b1f)")
-                .required(true),
-        )
-        .arg(Arg::new("files").help("files to search").required(true).num_args(1..))
-        .get_matches();
-
-    let mut pattern = String::new();
-    let bytes = matches
-        .get_one::<String>("bytes")
-        .unwrap()
-        .trim()
-        .to_lowercase();
-
-    let check_byte_or_exit = |byte| {
-        if u8::from_str_radix(byte, 16).is_err() {
-            eprintln!("{byte} isn't a hexadecimal byte.");
+// The files to search given as the arguments and by --files-from,
+// filtered by --include and --exclude
+fn collect_paths(matches: &ArgMatches, printer: &Printer) -> Vec<String> {
+    // TODO: add support of reading stdin
+    let mut paths = Vec::new();
+    // with -p, --pattern-file or --dump, the first positional argument is a file rather than the bytes
+    if explicit_patterns(matches) {
+        paths.extend(matches.get_one::<String>("bytes").cloned());
+    }
+    paths.extend(
+        matches
+            .get_many::<String>("files")
+            .unwrap_or_default()
+            .cloned(),
+    );
+    if let Some(files_from) = matches.get_one::<String>("files-from") {
+        let mut list = Vec::new();
+        let read = if files_from == "-" {
+            io::stdin().read_to_end(&mut list)
+        } else {
+            File::open(files_from).and_then(|mut file| file.read_to_end(&mut list))
+        };
+        if let Err(err) = read {
+            printer.error(&format!("Failed to read files from {files_from}: {err}"));
             exit(-1);
         }
+        paths.extend(parse_file_list(&list, matches.get_flag("null")));
+    }
+    // clap can't tell that the bytes is a file with -p
+    if paths.is_empty() && !matches.contains_id("files-from") && !matches.get_flag("validate") {
+        printer.error("No file to search.");
+        exit(-1);
+    }
+    // a file is searched if it matches any of --include, and none of --exclude
+    let globs = |id| {
+        Globs::new(matches.get_many::<String>(id).unwrap_or_default()).unwrap_or_else(|err| {
+            printer.error(&err);
+            exit(-1);
+        })
     };
-
-    // bytes in format "0x088b1f"
-    if bytes.starts_with("0x") {
-        // trim off "0x" first
-        let mut bytes = bytes.strip_prefix("0x").unwrap().to_string();
-        // prefix a '0' if the len isn't odd
-        if bytes.len() % 2 != 0 {
-            bytes.insert(0, '0');
+    let (include, exclude) = (globs("include"), globs("exclude"));
+    paths.retain(|path| {
+        let searched = (include.is_empty() || include.is_match(path)) && !exclude.is_match(path);
+        if !searched {
+            debug!(path, "skip the file filtered by --include or --exclude");
         }
-        assert!(bytes.len() % 2 == 0);
-        match bytes.len() {
-            2 => {
-                // a single byte, endianness doesn't matter
-                pattern = bytes;
-            }
-            _not_shorter_than_4 => {
-                for i in (0..bytes.len()).step_by(2) {
-                    let byte = &bytes[i..=i + 1];
-                    check_byte_or_exit(byte);
-                    // only need to swap bytes when it's litten-endian
-                    if matches.get_one::<String>("endian").unwrap() == "little" {
-                        pattern.insert_str(0, &(String::from(r"\x") + byte));
-                    } else {
-                        pattern += &(String::from(r"\x") + byte);
-                    }
-                }
-            }
-        }
-    } else {
-        // bytes in format "1f 8b 08"
-        bytes.split_whitespace().for_each(|byte| {
-            check_byte_or_exit(byte);
-            // prefix a '0' if the len isn't 2 for regex matching
-            // after checking the byte is a valid u8 hexadecimal,
-            // it's safe to check the len is 1 or not.
-            if byte.len() == 1 {
-                pattern += &(String::from(r"\x0") + byte);
-            } else {
-                pattern += &(String::from(r"\x") + byte);
-            }
-        });
+        searched
+    });
+    // only the bytes appended to one regular file can be searched again
+    if matches.get_flag("follow")
+        && (paths.len() != 1
+            || !fs::metadata(&paths[0]).is_ok_and(|metadata| metadata.is_file())
+            || (paths[0].ends_with(".gz") && !matches.get_flag("no-decompress")))
+    {
+        printer.error("--follow only works with one regular uncompressed file.");
+        exit(-1);
     }
+    paths
+}
 
-    // TODO: add support of reading stdin
-    let paths = matches.get_many::<String>("files").unwrap();
-    paths.for_each(|path| {
-        let mut file = match File::open(path) {
-            Ok(image) => image,
-            Err(err) => {
-                eprintln!("Failed to open file {path}: {err}");
-                return;
-            }
-        };
+fn main() {
+    let matches = get_matches();
 
-        let filelen = file.metadata().unwrap().len();
+    // trace the search on stderr for diagnosis, e.g.: RUST_LOG=hexsearch=debug,
+    // which is also enabled by -vv unless RUST_LOG is given
+    let filter = if matches.get_count("verbose") > 1 && env::var_os("RUST_LOG").is_none() {
+        EnvFilter::new("hexsearch=debug")
+    } else {
+        EnvFilter::from_default_env()
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
 
-        println!("{G_VT_BOLD}{path}{G_VT_DEFAULT}:\n");
-        if let Ok(offsets) = search_regex(&file, &pattern) {
-            let context = matches.get_one::<u8>("context").unwrap_or(&0);
-            // width argument has default value so it's safe to unwrap
-            let line_width = *matches.get_one::<u8>("width").unwrap() as usize;
+    if let Some(shell) = matches.get_one::<Shell>("generate-completions") {
+        generate(
+            *shell,
+            &mut build_cli(),
+            env!("CARGO_BIN_NAME"),
+            &mut io::stdout(),
+        );
+        return;
+    }
 
-            offsets.iter().for_each(|offset| {
-                println!("offset: {offset} ({offset:08x})");
-                let line_offset = offset - offset % line_width;
+    // the output is a terminal only without --output-file
+    let terminal = !matches.contains_id("output-file") && io::stdout().is_terminal();
+    let line_width = matches
+        .get_one::<u8>("width")
+        .map(|width| *width as usize)
+        .unwrap_or_else(|| {
+            // keep the default width for pipes and files
+            if !terminal {
+                return 16;
+            }
+            terminal_size().map_or(16, |(Width(columns), _)| auto_line_width(columns as usize))
+        });
+    let color = use_color(&matches, terminal);
+    // highlight argument has default value so it's safe to unwrap
+    let highlight = match matches.get_one::<String>("highlight").unwrap().as_str() {
+        "bold" => HighlightStyle::Bold,
+        "underline" => HighlightStyle::Underline,
+        "invert" => HighlightStyle::Invert,
+        _color => HighlightStyle::Color,
+    };
+    let printer = build_printer(&matches, line_width, color, highlight);
 
-                // print before-context lines
-                for i in (1..=*context).step_by(1).rev() {
-                    if line_offset < line_width * i as usize {
-                        continue;
-                    }
-                    read_and_print_one_line(
-                        &mut file,
-                        line_width,
-                        line_offset - line_width * i as usize,
-                        Range::default(),
-                    );
-                }
+    // compare two files line by line and print the lines where their bytes differ
+    if let Some(paths) = matches.get_many::<String>("diff") {
+        let paths = paths.cloned().collect::<Vec<_>>();
+        let buffer_size = matches
+            .get_one::<u64>("buffer-size")
+            .map_or(DEFAULT_BUFFER_SIZE, |size| *size as usize);
+        let context = matches.get_one::<u8>("context");
+        let lines = |id| *matches.get_one::<u8>(id).or(context).unwrap_or(&0) as usize;
+        // context-separator argument has default value so it's safe to unwrap
+        let separator = matches.get_one::<String>("context-separator").unwrap();
+        let differing = diff(
+            &printer,
+            &paths,
+            line_width,
+            buffer_size,
+            (lines("before-context"), lines("after-context")),
+            separator,
+        );
+        // exit with 1 if the files differ, similar to cmp
+        exit(if differing > 0 { 1 } else { 0 });
+    }
 
-                // each byte in pattern is represented as 4 characters, e.g.: "\xAA"
-                let bytes = pattern.len() / 4;
-                let byte_offset_start = offset % line_width;
-                // byte_offset_end is the offset of ending color byte (exclusive) in its own line,
-                // which might be different from the line of byte_offset_start
-                let byte_offset_end = (byte_offset_start + bytes) % line_width;
-                // when pattern ends at the end of the line, set the byte_offset_end to line width
-                // so that printing function can work properly
-                let byte_offset_end = if byte_offset_end == 0 {
-                    line_width
-                } else {
-                    byte_offset_end
-                };
+    let paths = collect_paths(&matches, &printer);
 
-                // calculate how many lines the pattern overlaps
-                let color_lines = {
-                    // not start at the line beginning and overlap the line ending
-                    let (start_line, remaining_bytes) = if byte_offset_start % line_width != 0
-                        && byte_offset_start + bytes > line_width
-                    {
-                        (1, bytes - (line_width - byte_offset_start))
-                    } else {
-                        (0, bytes)
-                    };
+    // hex-dump the bytes in start..end of each file without searching
+    if let Some(range) = matches.get_many::<usize>("dump") {
+        let range = range.copied().collect::<Vec<_>>();
+        if !dump(&printer, &paths, (range[0], range[1]), line_width) {
+            exit(-1);
+        }
+        return;
+    }
 
-                    start_line + (remaining_bytes + line_width - 1) / line_width
-                };
-                // print color lines
-                for i in (0..color_lines).step_by(1) {
-                    read_and_print_one_line(
-                        &mut file,
-                        line_width,
-                        line_offset + line_width * i,
-                        Range {
-                            start: if i == 0 { byte_offset_start } else { 0 },
-                            end: if i == color_lines - 1 {
-                                byte_offset_end
-                            } else {
-                                line_width
-                            },
-                        },
-                    )
-                }
+    let patterns = Patterns::new(&matches, &printer);
+    let reporter = Reporter::new(
+        &matches, &printer, &patterns, line_width, color, highlight, terminal,
+    );
+    reporter.print_csv_header();
 
-                // move line_offset pointing to next line of color lines
-                let line_offset = line_offset + line_width * color_lines;
-                // print after-context lines
-                for i in (0..*context).step_by(1) {
-                    // only check the start offset of the line
-                    // and let read_and_print_one_line() handle the end offset of this line
-                    if line_offset + line_width * i as usize >= filelen as usize {
-                        println!("(EOF)");
-                        break;
-                    }
-                    read_and_print_one_line(
-                        &mut file,
-                        line_width,
-                        line_offset + line_width * i as usize,
-                        Range::default(),
-                    );
-                }
+    let started = Instant::now();
+    // page the output of an interactive search, while the errors still go to the terminal
+    if let Some(pager) = matches.get_one::<String>("pager").filter(|_| terminal) {
+        let command = Some(pager.clone())
+            .filter(|pager| !pager.trim().is_empty())
+            .or_else(|| {
+                env::var("PAGER")
+                    .ok()
+                    .filter(|pager| !pager.trim().is_empty())
+            })
+            // -R keeps the colors
+            .unwrap_or_else(|| String::from("less -R"));
+        if let Err(err) = printer.page(&command) {
+            printer.error(&format!("Failed to run the pager {command}: {err}"));
+            exit(-1);
+        }
+    }
 
-                println!();
-            });
-        } else {
-            eprintln!("Cannot find the bytes: {bytes}\n");
+    #[cfg(feature = "async")]
+    let mut prefetched = reporter.prefetch(&paths);
+    #[cfg(not(feature = "async"))]
+    let mut prefetched = HashMap::<String, _>::new();
+    let mut totals = Totals::default();
+    for path in &paths {
+        if (totals.files_matched > 0 && printer.quiet > 0) || totals.interrupted_at.is_some() {
+            break;
         }
-    });
-}
+        reporter.search_file(path, prefetched.remove(path), &mut totals);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // exit with 130 like a shell after the signal, where the summary would be incomplete
+    if let Some((path, scanned)) = &totals.interrupted_at {
+        // reset the attributes in case the terminal is left in the color of a highlight
+        printer.print(&printer.style.reset);
+        printer.finish();
+        printer.error(&format!(
+            "Interrupted after scanning {scanned} bytes of {path}, {} match(es) so far.",
+            totals.matches
+        ));
+        exit(130);
+    }
 
-    #[test]
-    fn test_search_regex() {
-        let file = File::open("tests/data/vmlinuz-6.4-x86_64").unwrap();
-        let offsets = search_regex(&file, r"\x1f\x8b\x08").unwrap();
-        assert_eq!(offsets, vec![0x0061bd72, 0x006b7b9e, 0x0085ab9f]);
+    if matches.get_flag("follow") {
+        reporter.follow(&paths[0], &mut totals);
+    }
+    printer.finish();
+    totals.print_summary(&printer, matches.get_flag("stats"), started);
+
+    // exit with 3 if any file fails while searching, which tells it from no match
+    if totals.files_unreadable > 0 {
+        exit(3);
+    }
+    if totals.failed {
+        exit(-1);
+    }
+    // exit with 1 if the bytes can't be found in any file, similar to grep
+    if totals.files_matched == 0 {
+        exit(1);
     }
 }
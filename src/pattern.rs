@@ -0,0 +1,855 @@
+use clap::ArgMatches;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    process::exit,
+};
+
+use crate::{
+    cli::explicit_patterns,
+    printer::{eprint_line, Printer},
+    search::DEFAULT_BUFFER_SIZE,
+};
+
+// Collect the patterns in a pattern file, one per line, skipping the empty lines
+// and the comment lines starting with #
+pub(crate) fn parse_pattern_file(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+// Convert the quoted ASCII at the start of the text, e.g.: 'GIF89a' or "a\x00\\", into the bytes,
+// returning them along with the rest of the text after the closing quote
+pub(crate) fn parse_quoted(text: &str) -> Result<(Vec<u8>, &str), String> {
+    let quote = text.chars().next().unwrap();
+    // there's no escaped quote, so the next quote is always the closing one
+    let end = text[1..]
+        .find(quote)
+        .ok_or(format!("{text} isn't terminated by {quote}."))?
+        + 1;
+    let token = &text[..=end];
+    let mut bytes = Vec::new();
+    let mut chars = text[1..end].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\\') => bytes.push(b'\\'),
+                Some('x') => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    if hex.len() != 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(format!("\\x{hex} isn't an escaped byte in {token}."));
+                    }
+                    bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+                }
+                _ => return Err(format!("Only \\xNN and \\\\ can be escaped in {token}.")),
+            },
+            c if c.is_ascii() => bytes.push(c as u8),
+            c => return Err(format!("{c} isn't an ASCII character in {token}.")),
+        }
+    }
+    Ok((bytes, &text[end + 1..]))
+}
+
+// Expand the spaced hexadecimal bytes into the values of bytes, where ?? is any byte (None),
+// a suffix *N repeats the byte N times and a quoted ASCII text is its bytes,
+// e.g.: "ff*32 de ad ??*8" or "00 2c 'GIF89a' 00"
+pub(crate) fn parse_spaced_hex(bytes: &str) -> Result<Vec<Option<u8>>, String> {
+    // the longest run of a byte, which is already more than any sensible padding
+    const MAX_REPEAT: usize = 64 * 1024;
+    let mut values = Vec::new();
+    let mut rest = bytes.trim_start();
+    while !rest.is_empty() {
+        if rest.starts_with(['\'', '"']) {
+            let (text, remainder) = parse_quoted(rest)?;
+            values.extend(text.into_iter().map(Some));
+            rest = remainder.trim_start();
+            continue;
+        }
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '\'' || c == '"')
+            .unwrap_or(rest.len());
+        let (token, remainder) = rest.split_at(end);
+        rest = remainder.trim_start();
+        let (byte, count) = match token.split_once('*') {
+            Some((byte, count)) => (
+                byte,
+                count
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|count| (1..=MAX_REPEAT).contains(count))
+                    .ok_or(format!(
+                        "{count} isn't a repeat count of 1-{MAX_REPEAT} in {token}."
+                    ))?,
+            ),
+            None => (token, 1),
+        };
+        let value = if byte == "??" {
+            None
+        } else {
+            Some(
+                u8::from_str_radix(byte, 16)
+                    .map_err(|_| format!("{byte} isn't a hexadecimal byte."))?,
+            )
+        };
+        values.extend(std::iter::repeat_n(value, count));
+    }
+    Ok(values)
+}
+
+// Encode an integer of bits in decimal or hexadecimal prefixed with 0x, optionally negative
+// (e.g.: "-1" or "-0x01"), into the bytes in big endian, where a negative value is in two's
+// complement, so that both of 255 and -1 are ff in 8 bits unless it must be signed
+pub(crate) fn parse_int(value: &str, bits: u32, signed: bool) -> Result<Vec<u8>, String> {
+    let trimmed = value.trim().to_lowercase();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed.as_str()),
+    };
+    let magnitude = if let Some(hex) = digits.strip_prefix("0x") {
+        i128::from_str_radix(hex, 16)
+    } else {
+        digits.parse::<i128>()
+    }
+    .map_err(|_| format!("{value} isn't a decimal or hexadecimal (prefixed with 0x) integer"))?;
+    let number = if negative { -magnitude } else { magnitude };
+    let min = -(1i128 << (bits - 1));
+    let max = if signed {
+        (1i128 << (bits - 1)) - 1
+    } else {
+        (1i128 << bits) - 1
+    };
+    if !(min..=max).contains(&number) {
+        return Err(format!(
+            "{value} isn't in the range {min} to {max} of {bits}-bit integers"
+        ));
+    }
+    let bytes = (number as u128).to_be_bytes();
+    Ok(bytes[bytes.len() - bits as usize / 8..].to_vec())
+}
+
+// Convert a template of space-separated type:value fields into the spaced hexadecimal bytes,
+// where the types are u8, u16_le, u16_be, u32_le, u32_be, u64_le and u64_be, and the value *
+// is any value of the type, e.g.: "u32_le:0x12345678 u8:0xff u16_be:*" is "78 56 34 12 ff ??*2"
+pub(crate) fn parse_struct(template: &str) -> Result<String, String> {
+    let fields = template
+        .split_whitespace()
+        .map(|field| {
+            let (kind, value) = field
+                .split_once(':')
+                .ok_or(format!("{field} isn't a field in the format type:value"))?;
+            let (bits, little) = match kind {
+                "u8" => (8, false),
+                "u16_le" => (16, true),
+                "u16_be" => (16, false),
+                "u32_le" => (32, true),
+                "u32_be" => (32, false),
+                "u64_le" => (64, true),
+                "u64_be" => (64, false),
+                _ => {
+                    return Err(format!(
+                        "{kind} isn't one of the types u8, u16_le, u16_be, u32_le, u32_be, u64_le and u64_be"
+                    ))
+                }
+            };
+            if value == "*" {
+                return Ok(format!("??*{}", bits / 8));
+            }
+            let mut bytes = parse_int(value, bits, false)?;
+            if little {
+                bytes.reverse();
+            }
+            Ok(bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" "))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if fields.is_empty() {
+        return Err(String::from("No field in the template"));
+    }
+    Ok(fields.join(" "))
+}
+
+// Parse the replacement bytes either in format "1f 8b 08" (without ??) or in format "0x088b1f",
+// which is in the endianness like the positional bytes
+pub(crate) fn parse_replacement(bytes: &str, little: bool) -> Result<Vec<u8>, String> {
+    let bytes = bytes.trim();
+    if let Some(hex) = bytes.to_lowercase().strip_prefix("0x") {
+        let hex = if hex.len().is_multiple_of(2) {
+            hex.to_string()
+        } else {
+            format!("0{hex}")
+        };
+        let mut values = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                u8::from_str_radix(&hex[i..i + 2], 16)
+                    .map_err(|_| format!("{} isn't a hexadecimal byte.", &hex[i..i + 2]))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if little {
+            values.reverse();
+        }
+        Ok(values)
+    } else {
+        // the quoted ASCII keeps its cases
+        parse_spaced_hex(bytes)?
+            .into_iter()
+            .map(|value| value.ok_or(String::from("?? isn't allowed in the replacement.")))
+            .collect()
+    }
+}
+
+// Build the regex of the values of bytes, where None matches any byte,
+// and each byte is matched with the mask of the same index if given
+pub(crate) fn bytes_regex(values: &[Option<u8>], masks: Option<&[u8]>) -> String {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match (value, masks) {
+            (None, _) => String::from("(?s:.)"),
+            (Some(value), Some(masks)) => masked_byte_class(*value, masks[i]),
+            (Some(value), None) => format!(r"\x{value:02x}"),
+        })
+        .collect()
+}
+
+// Return the most bytes a match of the regex can span, or None if it's unbounded
+pub(crate) fn regex_max_len(pattern: &str, unicode: bool) -> Result<Option<usize>, String> {
+    fn max_len(hir: &Hir) -> Option<usize> {
+        match hir.kind() {
+            HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => Some(0),
+            HirKind::Literal(Literal::Unicode(char)) => Some(char.len_utf8()),
+            HirKind::Literal(Literal::Byte(_)) | HirKind::Class(Class::Bytes(_)) => Some(1),
+            // the ranges are sorted so the last one has the longest character
+            HirKind::Class(Class::Unicode(class)) => Some(
+                class
+                    .ranges()
+                    .last()
+                    .map_or(0, |range| range.end().len_utf8()),
+            ),
+            HirKind::Repetition(repetition) => {
+                let len = max_len(&repetition.hir)?;
+                let count = match &repetition.kind {
+                    RepetitionKind::ZeroOrOne => 1,
+                    RepetitionKind::Range(RepetitionRange::Exactly(count))
+                    | RepetitionKind::Range(RepetitionRange::Bounded(_, count)) => *count as usize,
+                    _unbounded if len == 0 => 0,
+                    _unbounded => return None,
+                };
+                len.checked_mul(count)
+            }
+            HirKind::Group(group) => max_len(&group.hir),
+            HirKind::Concat(hirs) => hirs
+                .iter()
+                .try_fold(0usize, |sum, hir| sum.checked_add(max_len(hir)?)),
+            HirKind::Alternation(hirs) => hirs
+                .iter()
+                .try_fold(0, |max, hir| Some(max_len(hir)?.max(max))),
+        }
+    }
+    let hir = regex_syntax::ParserBuilder::new()
+        .unicode(unicode)
+        .allow_invalid_utf8(true)
+        .build()
+        .parse(pattern)
+        .map_err(|err| err.to_string())?;
+    Ok(max_len(&hir))
+}
+
+// Build a regex character class which matches every byte b where (b & mask) == (value & mask)
+pub(crate) fn masked_byte_class(value: u8, mask: u8) -> String {
+    let mut class = String::from("[");
+    (0..=u8::MAX)
+        .filter(|byte| byte & mask == value & mask)
+        .for_each(|byte| class += &format!(r"\x{byte:02x}"));
+    class.push(']');
+    class
+}
+
+// Print the error of a malformed pattern and exit with 2 like grep
+fn malformed(printer: &Printer, message: &str) -> ! {
+    printer.error(message);
+    exit(2);
+}
+
+// Convert the bytes argument into a regex and the number of bytes it matches
+pub(crate) fn parse_bytes(text: &str, matches: &ArgMatches, printer: &Printer) -> (String, usize) {
+    let regex = matches.get_flag("regex");
+    let unicode = matches.get_flag("unicode");
+    let buffer_size = matches
+        .get_one::<u64>("buffer-size")
+        .map_or(DEFAULT_BUFFER_SIZE, |size| *size as usize);
+    // the bytes in format "1f 8b 08" are swapped in words only in little endian
+    let word_size = if matches.get_one::<String>("endian").unwrap() == "little" {
+        // word-size argument has default value so it's safe to unwrap
+        matches
+            .get_one::<String>("word-size")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap()
+    } else {
+        1
+    };
+    // from_str_radix() also accepts a sign like "+f", so check the digits instead
+    let check_byte_or_exit = |byte: &str, text: &str| {
+        if byte.len() != 2 || !byte.chars().all(|c| c.is_ascii_hexdigit()) {
+            malformed(
+                printer,
+                &format!("{byte} isn't a hexadecimal byte in {text}."),
+            );
+        }
+    };
+
+    let mut pattern = String::new();
+    let mut pattern_len = 0;
+    let bytes = text.trim().to_lowercase();
+    if regex {
+        // the original argument is the regex, where the cases matter
+        pattern = text.to_string();
+        // a match longer than the buffer may be missed around the chunk boundaries
+        pattern_len = match regex_max_len(text, unicode) {
+            // which is checked against the buffer size later
+            Ok(max_len) => max_len.unwrap_or(buffer_size).max(1),
+            // the regex is the original argument
+            Err(err) => malformed(
+                printer,
+                &format!("Invalid pattern: {text}\nregex: {text}\n{err}"),
+            ),
+        };
+    } else if matches.get_flag("string-utf16") || matches.get_flag("string-utf16be") {
+        // the bytes are a text to be encoded in UTF-16 and searched,
+        // so use the original argument rather than the trimmed and lowercased one
+        text.encode_utf16().for_each(|unit| {
+            let bytes = if matches.get_flag("string-utf16be") {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            };
+            bytes.iter().for_each(|byte| {
+                pattern += &format!(r"\x{byte:02x}");
+                pattern_len += 1;
+            });
+        });
+    } else if matches.get_flag("octal") || matches.get_flag("decimal") {
+        // bytes in format "037 213 010" or "31 139 8"
+        let (radix, base) = if matches.get_flag("octal") {
+            (8, "an octal")
+        } else {
+            (10, "a decimal")
+        };
+        bytes.split_whitespace().for_each(|byte| {
+            // from_str_radix() also rejects values out of the range 0-255
+            if let Ok(byte) = u8::from_str_radix(byte, radix) {
+                pattern += &format!(r"\x{byte:02x}");
+                pattern_len += 1;
+            } else {
+                malformed(printer, &format!("{byte} isn't {base} byte."));
+            }
+        });
+    } else if bytes.starts_with("0x") {
+        if matches.contains_id("mask") {
+            printer.error("--mask only works with bytes in format \"1f 8b 08\".");
+            exit(-1);
+        }
+        // bytes in format "0x088b1f"
+        // trim off "0x" first
+        let mut bytes = bytes.strip_prefix("0x").unwrap().to_string();
+        // prefix a '0' if the len is odd, so that the value is read like a number
+        // (e.g.: 0x12345 is 0x012345) before it's swapped in little endian
+        if !bytes.len().is_multiple_of(2) {
+            bytes.insert(0, '0');
+        }
+        pattern_len = bytes.len() / 2;
+        // split the bytes rather than the characters, where a non-ASCII one is rejected
+        for pair in bytes.as_bytes().chunks(2) {
+            let byte = String::from_utf8_lossy(pair);
+            check_byte_or_exit(&byte, text.trim());
+            // only need to swap bytes when it's little-endian,
+            // and the endianness doesn't matter for a single byte
+            if matches.get_one::<String>("endian").unwrap() == "little" {
+                pattern.insert_str(0, &(String::from(r"\x") + &byte));
+            } else {
+                pattern += &(String::from(r"\x") + &byte);
+            }
+        }
+    } else {
+        // bytes in format "1f 8b 08", or with repeat counts and wildcards "ff*32 de ad ??*8",
+        // or with quoted ASCII "00 2c 'GIF89a' 00" whose cases matter
+        let mut values =
+            parse_spaced_hex(text.trim()).unwrap_or_else(|err| malformed(printer, &err));
+        pattern_len = values.len();
+        if !values.len().is_multiple_of(word_size) {
+            malformed(
+                printer,
+                &format!(
+                    "The {} bytes of {} aren't words of {word_size} bytes.",
+                    values.len(),
+                    text.trim()
+                ),
+            );
+        }
+
+        let masks = matches.get_one::<String>("mask").map(|mask| {
+            let masks = parse_spaced_hex(mask).unwrap_or_else(|err| malformed(printer, &err));
+            if masks.len() != values.len() {
+                malformed(
+                    printer,
+                    &format!(
+                        "The mask has {} bytes but the pattern has {} bytes.",
+                        masks.len(),
+                        values.len()
+                    ),
+                );
+            }
+            let mut masks = masks
+                .iter()
+                .map(|mask| {
+                    mask.unwrap_or_else(|| malformed(printer, "?? isn't allowed in the mask."))
+                })
+                .collect::<Vec<_>>();
+            // the mask is swapped along with the bytes
+            masks.chunks_mut(word_size).for_each(<[u8]>::reverse);
+            masks
+        });
+        values
+            .chunks_mut(word_size)
+            .for_each(<[Option<u8>]>::reverse);
+        pattern = bytes_regex(&values, masks.as_deref());
+    }
+    (pattern, pattern_len)
+}
+
+// Format the bytes in the format "1f 8b 08" to be parsed like a bytes argument
+fn spaced_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// The patterns to search, built from the bytes argument, -p, --pattern-file
+// and the other arguments giving the bytes of a value
+pub(crate) struct Patterns {
+    // the patterns as given by the user for the messages, and their names in the records
+    // which are their labels if any
+    pub(crate) texts: Vec<String>,
+    pub(crate) labels: Vec<Option<String>>,
+    pub(crate) names: Vec<String>,
+    pub(crate) labeled: bool,
+    // the regex of each pattern and the number of bytes it matches
+    pub(crate) regexes: Vec<(String, usize)>,
+    // the regex matching any of the patterns, where the first one wins at the same offset,
+    // and the longest of them
+    pub(crate) pattern: String,
+    pub(crate) pattern_len: usize,
+    // all patterns as given by the user
+    pub(crate) original_bytes: String,
+    // the patterns of --pattern-file are searched separately unless --or is given
+    pub(crate) separate: bool,
+    // anchored patterns to tell which one of them matches at an offset,
+    // which aren't built for a single (and maybe long) pattern
+    pub(crate) anchored: Vec<Option<Regex>>,
+    // the regex of each pattern to find all of its occurrences in the lines printed
+    pub(crate) occurring: Vec<Regex>,
+    // the width and the value of --f32 or --f64, and the difference allowed to match it
+    pub(crate) float: Option<(usize, f64)>,
+    pub(crate) float_epsilon: Option<f64>,
+    pub(crate) near_pattern: Option<(String, usize)>,
+    pub(crate) then_pattern: Option<(String, usize)>,
+    // with --then, both patterns are searched in one pass and their matches are told apart
+    // by the anchored patterns
+    pub(crate) then_search: Option<(String, usize, Option<Regex>, Option<Regex>)>,
+}
+
+impl Patterns {
+    // Build the patterns from the arguments, and exit after checking them with --validate
+    pub(crate) fn new(matches: &ArgMatches, printer: &Printer) -> Patterns {
+        let unicode = matches.get_flag("unicode");
+        let mut texts = matches
+            .get_many::<String>("pattern")
+            .unwrap_or_default()
+            .cloned()
+            .collect::<Vec<_>>();
+        // each --label names the --pattern given right before it
+        let mut labels: Vec<Option<String>> = vec![None; texts.len()];
+        let pattern_indices = matches
+            .indices_of("pattern")
+            .map(|indices| indices.collect::<Vec<_>>())
+            .unwrap_or_default();
+        if let (Some(indices), Some(names)) = (
+            matches.indices_of("label"),
+            matches.get_many::<String>("label"),
+        ) {
+            for (index, label) in indices.zip(names) {
+                let Some(i) = pattern_indices.iter().rposition(|pattern| *pattern < index) else {
+                    printer.error(&format!("--label {label} must follow a --pattern."));
+                    exit(-1);
+                };
+                if labels[i].is_some() {
+                    printer.error(&format!(
+                        "The pattern {} has more than one --label.",
+                        texts[i]
+                    ));
+                    exit(-1);
+                }
+                labels[i] = Some(label.clone());
+            }
+        }
+        if let Some(pattern_file) = matches.get_one::<String>("pattern-file") {
+            let contents = match fs::read_to_string(pattern_file) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    printer.error(&format!(
+                        "Failed to read patterns from {pattern_file}: {err}"
+                    ));
+                    exit(-1);
+                }
+            };
+            let file_patterns = parse_pattern_file(&contents);
+            if file_patterns.is_empty() {
+                printer.error(&format!(
+                    "No pattern in {pattern_file}, which should have one pattern per line."
+                ));
+                exit(-1);
+            }
+            texts.extend(file_patterns);
+        }
+        // the value of --f32 or --f64 is searched as its bytes
+        let little = matches.get_one::<String>("endian").unwrap() == "little";
+        let float = matches
+            .get_one::<f32>("f32")
+            .map(|value| (4, *value as f64))
+            .or(matches.get_one::<f64>("f64").map(|value| (8, *value)));
+        if let Some((width, value)) = float {
+            let bytes = match (width, little) {
+                (4, true) => (value as f32).to_le_bytes().to_vec(),
+                (4, false) => (value as f32).to_be_bytes().to_vec(),
+                (_, true) => value.to_le_bytes().to_vec(),
+                (_, false) => value.to_be_bytes().to_vec(),
+            };
+            texts.push(spaced_hex(&bytes));
+        }
+        [("int8", 8), ("int16", 16), ("int32", 32), ("int64", 64)]
+            .iter()
+            .filter_map(|(id, bits)| Some((matches.get_one::<String>(id)?, *bits)))
+            .for_each(|(value, bits)| {
+                let mut bytes = parse_int(value, bits, matches.get_flag("signed"))
+                    .unwrap_or_else(|err| malformed(printer, &err));
+                if little {
+                    bytes.reverse();
+                }
+                texts.push(spaced_hex(&bytes));
+            });
+        if let Some(uuid) = matches.get_one::<[u8; 16]>("uuid") {
+            let mut bytes = *uuid;
+            match matches.get_one::<String>("uuid-layout").unwrap().as_str() {
+                "mixed" => {
+                    bytes[0..4].reverse();
+                    bytes[4..6].reverse();
+                    bytes[6..8].reverse();
+                }
+                "le" => bytes.reverse(),
+                _ => {}
+            }
+            texts.push(spaced_hex(&bytes));
+        }
+        if let Some(codepoints) = matches.get_many::<char>("codepoint") {
+            texts.push(spaced_hex(codepoints.collect::<String>().as_bytes()));
+        }
+        if let Some(fields) = matches.get_one::<String>("struct") {
+            texts.push(fields.clone());
+        }
+        let float_epsilon = matches.get_one::<f64>("float-epsilon").copied();
+        if float_epsilon.is_some() && float.is_none() {
+            printer.error("--float-epsilon only works with --f32 or --f64.");
+            exit(-1);
+        }
+        if !explicit_patterns(matches) {
+            texts.push(matches.get_one::<String>("bytes").unwrap().clone());
+        }
+        let separate =
+            matches.contains_id("pattern-file") && !matches.get_flag("or") && texts.len() > 1;
+        let mut regexes = texts
+            .iter()
+            .map(|text| parse_bytes(text, matches, printer))
+            .collect::<Vec<_>>();
+        // the bytes of --needle-file are searched verbatim, bypassing the formats of bytes
+        if let Some(needle_file) = matches.get_one::<String>("needle-file") {
+            let range = matches.get_one::<(usize, usize)>("needle-range");
+            let needle = File::open(needle_file).and_then(|mut file| {
+                let (start, len) = range.copied().unwrap_or((0, usize::MAX));
+                file.seek(SeekFrom::Start(start as u64))?;
+                let mut needle = Vec::new();
+                file.take(len as u64).read_to_end(&mut needle)?;
+                Ok(needle)
+            });
+            let needle = match needle {
+                Ok(needle) => needle,
+                Err(err) => {
+                    printer.error(&format!(
+                        "Failed to read the needle from {needle_file}: {err}"
+                    ));
+                    exit(-1);
+                }
+            };
+            if let Some((start, len)) = range.filter(|(_, len)| needle.len() < *len) {
+                printer.error(&format!(
+                    "The needle file {needle_file} has no {len} bytes from {start}."
+                ));
+                exit(-1);
+            }
+            if needle.is_empty() {
+                printer.error(&format!("No byte in the needle file {needle_file}."));
+                exit(-1);
+            }
+            let values = needle.iter().map(|byte| Some(*byte)).collect::<Vec<_>>();
+            texts.push(needle_file.clone());
+            regexes.push((bytes_regex(&values, None), needle.len()));
+        }
+        // the bytes of --base64-pattern are searched verbatim as well, named by the base64 given
+        if let Some(bytes) = matches.get_one::<Vec<u8>>("base64-pattern") {
+            let values = bytes.iter().map(|byte| Some(*byte)).collect::<Vec<_>>();
+            // base64-pattern argument has a value so it's safe to unwrap
+            let raw = matches.get_raw("base64-pattern").unwrap().next().unwrap();
+            texts.push(raw.to_string_lossy().into_owned());
+            regexes.push((bytes_regex(&values, None), bytes.len()));
+        }
+        // the patterns without --label are named by their bytes
+        let labeled = labels.iter().any(Option::is_some);
+        labels.resize(texts.len(), None);
+        let names = texts
+            .iter()
+            .zip(&labels)
+            .map(|(text, label)| label.clone().unwrap_or_else(|| text.clone()))
+            .collect::<Vec<_>>();
+        // --anchor-start and --anchor-end are the same as \A and \z in the regex of each pattern
+        let (anchor_start, anchor_end) = (
+            matches.get_flag("anchor-start"),
+            matches.get_flag("anchor-end"),
+        );
+        if anchor_start || anchor_end {
+            for (pattern, _) in regexes.iter_mut() {
+                *pattern = format!(
+                    "{}(?:{pattern}){}",
+                    if anchor_start { r"\A" } else { "" },
+                    if anchor_end { r"\z" } else { "" }
+                );
+            }
+        }
+        let pattern = if regexes.len() == 1 {
+            regexes[0].0.clone()
+        } else {
+            regexes
+                .iter()
+                .map(|(pattern, _)| format!("(?:{pattern})"))
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+        let original_bytes = texts.join(", ");
+        if matches.get_flag("validate") {
+            match RegexBuilder::new(&pattern).unicode(unicode).build() {
+                Ok(_) => {
+                    if printer.quiet == 0 {
+                        printer.println(&format!("Pattern is valid: {pattern}"));
+                    }
+                    printer.flush();
+                    exit(0);
+                }
+                Err(err) => {
+                    printer.error(&format!(
+                        "Invalid pattern: {original_bytes}\nregex: {pattern}\n{err}"
+                    ));
+                    exit(2);
+                }
+            }
+        }
+        let pattern_len = regexes.iter().map(|(_, len)| *len).max().unwrap();
+        let anchored = |pattern: &str| {
+            RegexBuilder::new(&format!("^(?:{pattern})"))
+                .unicode(unicode)
+                .build()
+                .ok()
+        };
+        let occurring = regexes
+            .iter()
+            .filter(|_| matches.get_flag("highlight-all"))
+            .filter_map(|(pattern, _)| {
+                RegexBuilder::new(&format!("(?:{pattern})"))
+                    .unicode(unicode)
+                    .build()
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        let near_pattern = matches
+            .get_one::<String>("near-pattern")
+            .map(|text| parse_bytes(text, matches, printer));
+        let then_pattern = matches
+            .get_one::<String>("then")
+            .map(|text| parse_bytes(text, matches, printer));
+        // the regexes built from the bytes, as searched
+        if matches.get_count("verbose") > 0 && printer.quiet < 2 {
+            eprint_line(&format!("pattern: {pattern}"));
+            if let Some((near_pattern, _)) = &near_pattern {
+                eprint_line(&format!("near pattern: {near_pattern}"));
+            }
+            if let Some((then_pattern, _)) = &then_pattern {
+                eprint_line(&format!("then pattern: {then_pattern}"));
+            }
+        }
+        let then_search = then_pattern.as_ref().map(|(then, then_len)| {
+            (
+                format!("(?:{pattern})|(?:{then})"),
+                pattern_len.max(*then_len),
+                anchored(&pattern),
+                anchored(then),
+            )
+        });
+        Patterns {
+            anchored: regexes
+                .iter()
+                .filter(|_| regexes.len() > 1)
+                .map(|(pattern, _)| anchored(pattern))
+                .collect(),
+            texts,
+            labels,
+            names,
+            labeled,
+            regexes,
+            pattern,
+            pattern_len,
+            original_bytes,
+            separate,
+            occurring,
+            float,
+            float_epsilon,
+            near_pattern,
+            then_pattern,
+            then_search,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_int() {
+        assert_eq!(parse_int("255", 8, false), parse_int("-1", 8, false));
+        assert_eq!(parse_int("-1", 16, false), Ok(vec![0xff, 0xff]));
+        assert_eq!(parse_int("-0x01", 32, true), Ok(vec![0xff; 4]));
+        assert_eq!(parse_int("0x1234", 16, false), Ok(vec![0x12, 0x34]));
+        assert_eq!(
+            parse_int("-9223372036854775808", 64, true),
+            Ok(vec![0x80, 0, 0, 0, 0, 0, 0, 0])
+        );
+        assert!(parse_int("255", 8, true).is_err());
+        assert!(parse_int("256", 8, false).is_err());
+        assert!(parse_int("-129", 8, false).is_err());
+        assert!(parse_int("1f", 8, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_struct() {
+        assert_eq!(
+            parse_struct("u32_le:0x12345678 u8:0xFF u16_be:*"),
+            Ok(String::from("78 56 34 12 ff ??*2"))
+        );
+        assert_eq!(
+            parse_struct(" u16_le:-2  u64_be:1 "),
+            Ok(String::from("fe ff 00 00 00 00 00 00 00 01"))
+        );
+        assert!(parse_struct("").is_err());
+        assert!(parse_struct("u32:1").is_err());
+        assert!(parse_struct("u8").is_err());
+        assert!(parse_struct("u8:256").is_err());
+    }
+
+    #[test]
+    fn test_parse_pattern_file() {
+        assert_eq!(
+            parse_pattern_file("# gzip\n1f 8b 08\n\n  # zip\n  50 4b 03 04  \r\n0x4d5a"),
+            vec!["1f 8b 08", "50 4b 03 04", "0x4d5a"]
+        );
+        assert!(parse_pattern_file("# nothing\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_parse_replacement() {
+        assert_eq!(parse_replacement("90 90*2", false), Ok(vec![0x90; 3]));
+        assert_eq!(
+            parse_replacement("0x88b1f", false),
+            Ok(vec![0x08, 0x8b, 0x1f])
+        );
+        assert_eq!(
+            parse_replacement("0x088B1F", true),
+            Ok(vec![0x1f, 0x8b, 0x08])
+        );
+        assert!(parse_replacement("90 ??", false).is_err());
+        assert!(parse_replacement("0x9g", false).is_err());
+    }
+
+    #[test]
+    fn test_parse_spaced_hex() {
+        let values = parse_spaced_hex("ff*3 ??*2 DE").unwrap();
+        assert_eq!(
+            values,
+            vec![Some(0xff), Some(0xff), Some(0xff), None, None, Some(0xde)]
+        );
+        assert_eq!(bytes_regex(&values, None), r"\xff\xff\xff(?s:.)(?s:.)\xde");
+        assert_eq!(
+            bytes_regex(&[Some(0x1f), None], Some(&[0xff, 0x00])),
+            r"[\x1f](?s:.)"
+        );
+        assert!(parse_spaced_hex("ff*0").is_err());
+        assert!(parse_spaced_hex("ff*65537").is_err());
+        assert!(parse_spaced_hex("ff*x").is_err());
+        assert!(parse_spaced_hex("fg").is_err());
+
+        assert_eq!(
+            parse_spaced_hex(r#"00 2c 'GIF8' "\x00\\" ??"#).unwrap(),
+            vec![
+                Some(0x00),
+                Some(0x2c),
+                Some(b'G'),
+                Some(b'I'),
+                Some(b'F'),
+                Some(b'8'),
+                Some(0x00),
+                Some(b'\\'),
+                None
+            ]
+        );
+        assert!(parse_spaced_hex("00 'GIF").is_err());
+        assert!(parse_spaced_hex("'G\u{e9}'").is_err());
+        assert!(parse_spaced_hex(r"'\x0g'").is_err());
+        assert!(parse_spaced_hex(r"'\n'").is_err());
+    }
+
+    #[test]
+    fn test_regex_max_len() {
+        assert_eq!(regex_max_len(r"\x7fELF[\x01\x02]", false), Ok(Some(5)));
+        assert_eq!(regex_max_len(r"ab(cd|e)?\x00{2,4}", false), Ok(Some(8)));
+        assert_eq!(regex_max_len(r"^\bab", false), Ok(Some(2)));
+        assert_eq!(regex_max_len(r"\xff+", false), Ok(None));
+        assert_eq!(regex_max_len(r"\w", false), Ok(Some(1)));
+        assert_eq!(regex_max_len(r"\p{L}", true), Ok(Some(4)));
+        assert_eq!(regex_max_len("é", true), Ok(Some(2)));
+        assert!(regex_max_len("(", false).is_err());
+    }
+
+    #[test]
+    fn test_masked_byte_class() {
+        assert_eq!(masked_byte_class(0x1f, 0xff), r"[\x1f]");
+        assert_eq!(masked_byte_class(0x81, 0xfe), r"[\x80\x81]");
+    }
+}
@@ -0,0 +1,1007 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs::File,
+    io::{self, BufWriter, ErrorKind, Write},
+    ops::Range,
+    process::{self, exit, Child, Stdio},
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::search::ReadAt;
+
+pub(crate) const G_VT_DEFAULT: &str = "\x1B[0m";
+pub(crate) const G_VT_BOLD: &str = "\x1B[1m";
+// The colors of the file names and the offsets in --format grep, same as grep
+pub(crate) const G_VT_MAGENTA: &str = "\x1B[35m";
+pub(crate) const G_VT_GREEN: &str = "\x1B[32m";
+// The colors to highlight the matches of the patterns after the first one,
+// which is highlighted in --highlight-color
+pub(crate) const HIGHLIGHT_PALETTE: [&str; 6] =
+    ["red", "green", "yellow", "blue", "magenta", "cyan"];
+// The most matched bytes shown in --format grep, after which the rest is elided
+pub(crate) const MAX_GREP_BYTES: usize = 32;
+// Reproduce the "offset: N (0000000n), length: M" line printed before each hexdump,
+// where {offset} is in the base of --offset-base
+pub(crate) const DEFAULT_OUTPUT_FORMAT: &str = "offset: {offset_dec} ({offset}), length: {length}";
+// Print only the matched bytes similar to grep -o
+pub(crate) const ONLY_MATCHING_FORMAT: &str = "{file}: {offset_hex}: {hex_bytes}";
+// Print only the matched bytes of each match like a row of the hexdump
+pub(crate) const DUMP_MATCH_FORMAT: &str = "{offset}: {hex_bytes}  |{ascii}|";
+// Print only the offset of the line of the hexdump having each match
+pub(crate) const LINE_OFFSET_ONLY_FORMAT: &str = "{file}: 0x{offset_hex}";
+
+// Write the line to stderr, and exit once it fails like the output, e.g.: the pipe of
+// 2>&1 is closed by head, rather than panicking like eprintln!
+pub(crate) fn eprint_line(line: &str) {
+    if let Err(err) = writeln!(io::stderr(), "{line}") {
+        if err.kind() == ErrorKind::BrokenPipe {
+            exit(-1);
+        }
+    }
+}
+
+// Join the fields into a CSV row separated by the delimiter, where the fields containing
+// the delimiter, quotes or line breaks are quoted and the quotes are escaped by doubling them (RFC 4180)
+pub(crate) fn csv_row(fields: &[&str], delimiter: char) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            if field.contains([delimiter, '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string())
+}
+
+// Format the bytes as a C array, a Rust byte string or a Python bytes literal
+// named after the offset, with 12 bytes per line
+pub(crate) fn emit_snippet(language: &str, offset: usize, bytes: &[u8]) -> String {
+    let lines = bytes.chunks(12).map(|chunk| match language {
+        "c" => chunk
+            .iter()
+            .map(|byte| format!("0x{byte:02x},"))
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => chunk.iter().map(|byte| format!("\\x{byte:02x}")).collect(),
+    });
+    match language {
+        "c" => format!(
+            "static const uint8_t data_0x{offset:08x}[] = {{\n{}}};",
+            lines
+                .map(|line| format!("    {line}\n"))
+                .collect::<String>()
+        ),
+        "rust" => format!(
+            "const DATA_{offset:08X}: &[u8] = b\"\\\n{}\";",
+            lines
+                .map(|line| format!("    {line}"))
+                .collect::<Vec<_>>()
+                .join("\\\n")
+        ),
+        _python => format!(
+            "data_0x{offset:08x} = (\n{})",
+            lines
+                .map(|line| format!("    b\"{line}\"\n"))
+                .collect::<String>()
+        ),
+    }
+}
+
+// Decode the bytes as UTF-8 into one cell of the column of characters per byte, where
+// a character is put in the cell of its first byte and the cells of the rest are padded
+// with spaces to keep the column as wide as the bytes, e.g.: "é" is in "é" and " ".
+// The invalid sequences, the control characters and the characters wider than their bytes
+// (or without a width) are shown as '.' per byte, including those split across two lines
+pub(crate) fn utf8_cells(bytes: &[u8]) -> Vec<String> {
+    let mut cells = Vec::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for char in chunk.valid().chars() {
+            let len = char.len_utf8();
+            match char.width().filter(|width| (1..=len).contains(width)) {
+                Some(width) if !char.is_control() => {
+                    cells.push(char.to_string());
+                    cells.extend((1..len).map(|i| " ".repeat((i <= len - width) as usize)));
+                }
+                _ => cells.extend((0..len).map(|_| String::from("."))),
+            }
+        }
+        cells.extend(chunk.invalid().iter().map(|_| String::from(".")));
+    }
+    cells
+}
+
+// Return the Shannon entropy in bits per byte of the bytes counted by value,
+// which is 0 for a single repeated byte and 8 for evenly distributed bytes
+pub(crate) fn shannon_entropy(counts: &[usize; 256]) -> f64 {
+    let total = counts.iter().sum::<usize>() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// Return the Shannon entropy in bits per byte of the bytes
+pub(crate) fn bytes_entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0; 256];
+    bytes.iter().for_each(|byte| counts[*byte as usize] += 1);
+    shannon_entropy(&counts)
+}
+
+// Return the minimum, maximum and most common (the smallest one in a tie)
+// gaps between consecutive offsets, or None if there are less than 2 offsets
+pub(crate) fn gap_stats(offsets: &[usize]) -> Option<(usize, usize, usize)> {
+    let mut gaps = offsets
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect::<Vec<_>>();
+    gaps.sort_unstable();
+    let (&min, &max) = (gaps.first()?, gaps.last()?);
+    let mut common = (min, 0);
+    gaps.chunk_by(|a, b| a == b).for_each(|same| {
+        if same.len() > common.1 {
+            common = (same[0], same.len());
+        }
+    });
+    Some((min, max, common.0))
+}
+
+// A block of consecutive lines to print, which are merged from the overlapping
+// or adjacent lines of matches and their context
+#[derive(Debug, PartialEq)]
+pub(crate) struct Block {
+    // offsets of the first line and the line after the last line
+    pub(crate) lines: Range<usize>,
+    // indices of the matches in this block
+    pub(crate) matches: Vec<usize>,
+}
+
+// Add the lines of the i-th match of len bytes at offset and its context of
+// (before, after) lines to the blocks, where they are merged into the last block if overlapping
+pub(crate) fn push_block(
+    blocks: &mut Vec<Block>,
+    i: usize,
+    (offset, len): (usize, usize),
+    line_width: usize,
+    (before, after): (usize, usize),
+) {
+    let line_offset = offset - offset % line_width;
+    let start = line_offset.saturating_sub(line_width * before);
+    // the line after the last line the match overlaps
+    let end = (offset + len.max(1)).div_ceil(line_width) * line_width;
+    let end = end + line_width * after;
+    match blocks.last_mut() {
+        Some(last) if start <= last.lines.end => {
+            last.lines.end = last.lines.end.max(end);
+            last.matches.push(i);
+        }
+        _ => blocks.push(Block {
+            lines: start..end,
+            matches: vec![i],
+        }),
+    }
+}
+
+// Format a size in bytes with the largest suffix KiB, MiB or GiB dividing it (e.g.: "1MiB")
+pub(crate) fn format_size(size: usize) -> String {
+    [(30, "GiB"), (20, "MiB"), (10, "KiB")]
+        .iter()
+        .find(|(shift, _)| size > 0 && size.is_multiple_of(1 << shift))
+        .map_or_else(
+            || format!("{size}B"),
+            |(shift, suffix)| format!("{}{suffix}", size >> shift),
+        )
+}
+
+#[derive(Default)]
+pub(crate) enum OffsetBase {
+    #[default]
+    Hex,
+    Dec,
+    Oct,
+}
+
+impl OffsetBase {
+    // The digits of the largest 32-bit offset in the base
+    pub(crate) fn min_width(&self) -> usize {
+        match self {
+            OffsetBase::Hex => 8,
+            OffsetBase::Dec => 10,
+            OffsetBase::Oct => 11,
+        }
+    }
+}
+
+// How the matched bytes are highlighted, where bold, underline and invert
+// can be told apart without distinguishing colors
+#[derive(Clone, Copy)]
+pub(crate) enum HighlightStyle {
+    Color,
+    Bold,
+    Underline,
+    Invert,
+}
+
+impl HighlightStyle {
+    // The escape sequence of the style, where color is given by --highlight-color
+    pub(crate) fn escape(&self, color: &str) -> String {
+        match self {
+            HighlightStyle::Color => String::from(color),
+            HighlightStyle::Bold => String::from(G_VT_BOLD),
+            HighlightStyle::Underline => String::from("\x1B[4m"),
+            HighlightStyle::Invert => String::from("\x1B[7m"),
+        }
+    }
+}
+
+// The layout of the hexdump lines
+#[derive(Default)]
+pub(crate) enum Layout {
+    // "0061bd70  53 54 1f 8b 08 02 03 8c  3c c9 72 dc 38 b2 f7 f9  |ST......<.r.8...|"
+    #[default]
+    Canonical,
+    // the default layout of xxd, i.e.: "0061bd70: 5354 1f8b 0802 038c 3cc9 72dc 38b2 f7f9  ST......<.r.8..."
+    Xxd,
+}
+
+impl Layout {
+    // The separator after the offset of a line
+    pub(crate) fn offset_separator(&self) -> &str {
+        match self {
+            Layout::Canonical => "",
+            Layout::Xxd => ":",
+        }
+    }
+
+    // The separator before the group of bytes starting at the i-th byte,
+    // where xxd takes all bytes as one group if group_size is 0
+    pub(crate) fn group_separator(&self, i: usize, group_size: usize) -> &str {
+        match self {
+            // is_multiple_of(0) is only true for 0
+            Layout::Canonical if group_size != 0 && i.is_multiple_of(group_size) => " ",
+            Layout::Xxd if i.is_multiple_of(group_size) => " ",
+            _ => "",
+        }
+    }
+
+    // The prefix of each hexadecimal byte, which is highlighted with the byte
+    pub(crate) fn byte_prefix(&self) -> &str {
+        match self {
+            Layout::Canonical => " ",
+            Layout::Xxd => "",
+        }
+    }
+
+    // The text between the column of hexadecimal bytes and the column of characters,
+    // and the text after the latter
+    pub(crate) fn gutters(&self) -> (&str, &str) {
+        match self {
+            Layout::Canonical => ("  |", "|"),
+            Layout::Xxd => ("  ", ""),
+        }
+    }
+
+    // Whether the column of characters is padded with spaces after a short line
+    pub(crate) fn pad_characters(&self) -> bool {
+        matches!(self, Layout::Canonical)
+    }
+}
+
+// The record printed for each match instead of the hexdump
+#[derive(Clone, Copy)]
+pub(crate) enum RecordFormat<'a> {
+    // a row of --format csv, with the context bytes and the entropy in the extra columns
+    Csv {
+        delimiter: char,
+        context: bool,
+        entropy: bool,
+    },
+    // a line of --format grep
+    Grep,
+    // a line of --output-format or the formats of its shortcuts, where the offset is rounded
+    // down to the line of line_width bytes with --line-offset-only
+    Template {
+        format: &'a str,
+        line_width: Option<usize>,
+    },
+    // a snippet of --emit in the language
+    Emit(&'a str),
+}
+
+// One match printed as a record
+pub(crate) struct Record<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) offset: usize,
+    pub(crate) bytes: &'a [u8],
+    // the bytes dumped around the match, only printed in csv
+    pub(crate) context: &'a [u8],
+    // the pattern matching, which is in the columns of csv or the suffix of the other lines
+    pub(crate) columns: &'a [&'a str],
+    pub(crate) suffix: &'a str,
+}
+
+#[derive(Default)]
+pub(crate) struct Style {
+    pub(crate) bold: String,
+    pub(crate) highlight: String,
+    // the file names and the offsets in --format grep
+    pub(crate) file: String,
+    pub(crate) offset: String,
+    pub(crate) reset: String,
+}
+
+// Where the output goes, i.e.: stdout, --output-file or the stdin of --pager
+#[derive(Default)]
+pub(crate) enum Writer {
+    #[default]
+    Stdout,
+    File(BufWriter<File>),
+    Pager(Child),
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Stdout => io::stdout().write(buf),
+            Writer::File(file) => file.write(buf),
+            // the stdin of the pager is only taken when it's closed
+            Writer::Pager(pager) => pager.stdin.as_mut().unwrap().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Stdout => io::stdout().flush(),
+            Writer::File(file) => file.flush(),
+            Writer::Pager(pager) => pager.stdin.as_mut().unwrap().flush(),
+        }
+    }
+}
+
+// The output layer which prints the records and the hexdump lines
+#[derive(Default)]
+pub(crate) struct Printer {
+    // the output, while the errors are always printed to stderr
+    pub(crate) out: RefCell<Writer>,
+    // the file of --export-offsets and its path, which is written without buffering
+    // so that it's complete even when exiting early
+    pub(crate) export: Option<(String, File)>,
+    // escape sequences to style the output, which are all empty when color is disabled
+    pub(crate) style: Style,
+    // suppress all output to stdout, and also to stderr if it's given twice
+    pub(crate) quiet: u8,
+    // terminate each record with NUL instead of newline
+    pub(crate) null: bool,
+    // group the hexadecimal bytes every group_size bytes, 0 means no grouping
+    pub(crate) group_size: usize,
+    // the layout of the hexdump lines
+    pub(crate) layout: Layout,
+    // print only the column of hexadecimal bytes or characters
+    pub(crate) hex_only: bool,
+    pub(crate) ascii_only: bool,
+    // the base and the width of the offsets, which is set per file
+    pub(crate) offset_base: OffsetBase,
+    pub(crate) offset_width: Cell<usize>,
+    // added to the displayed offsets, e.g.: the address where a firmware is loaded,
+    // while the file is still read at the file offsets
+    pub(crate) base_address: usize,
+    // mark the highlighted bytes with characters as well, e.g.: without colors
+    pub(crate) marker: Option<char>,
+    // print the number of each line of the hexdump after the offset
+    pub(crate) line_numbers: bool,
+    // decode the column of characters as UTF-8 instead of ASCII
+    pub(crate) utf8: bool,
+}
+
+impl Printer {
+    // Substitute the tokens in format with the fields of one match
+    pub(crate) fn format_match(
+        &self,
+        format: &str,
+        path: &str,
+        offset: usize,
+        bytes: &[u8],
+    ) -> String {
+        let address = offset + self.base_address;
+        let hex_bytes = bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = bytes
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii() && !byte.is_ascii_control() {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        // expand each token once from left to right, so that the braces in the values
+        // substituted, e.g.: in the file name, are never taken as tokens
+        let mut line = String::new();
+        let mut rest = format;
+        while let Some(start) = rest.find('{') {
+            line += &rest[..start];
+            rest = &rest[start..];
+            let token = rest.find('}').map(|end| &rest[..=end]);
+            let value = match token.unwrap_or_default() {
+                "{file}" => path.to_string(),
+                "{offset_dec}" => address.to_string(),
+                "{offset_hex}" => format!("{address:08x}"),
+                "{offset}" => self.format_offset(offset),
+                "{length}" => bytes.len().to_string(),
+                "{hex_bytes}" => hex_bytes.clone(),
+                "{ascii}" => ascii.clone(),
+                "{entropy}" => format!("{:.2}", bytes_entropy(bytes)),
+                // keep the brace of an unknown token as it is
+                _ => {
+                    line.push('{');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            line += &value;
+            rest = &rest[token.unwrap_or_default().len()..];
+        }
+        line + rest
+    }
+
+    // Format the line printed before the hexdump of the match of len bytes at the offset,
+    // which only needs the length rather than the matched bytes
+    pub(crate) fn format_offset_line(&self, path: &str, offset: usize, len: usize) -> String {
+        let format = DEFAULT_OUTPUT_FORMAT.replace("{length}", &len.to_string());
+        self.format_match(&format, path, offset, &[])
+    }
+
+    // Format one match in a line like grep, where the long matches are elided
+    pub(crate) fn format_grep(&self, path: &str, offset: usize, bytes: &[u8]) -> String {
+        let shown = &bytes[..bytes.len().min(MAX_GREP_BYTES)];
+        let elided = if shown.len() < bytes.len() {
+            " ..."
+        } else {
+            ""
+        };
+        format!(
+            "{}{path}{}:{}0x{:08x}{}: {}{elided} |{}|",
+            self.style.file,
+            self.style.reset,
+            self.style.offset,
+            offset + self.base_address,
+            self.style.reset,
+            self.format_match("{hex_bytes}", path, offset, shown),
+            self.format_match("{ascii}", path, offset, shown),
+        )
+    }
+
+    // Format the record of one match, where the snippets of --emit have no suffix
+    pub(crate) fn format_record(&self, format: &RecordFormat, record: &Record) -> String {
+        let Record {
+            path,
+            offset,
+            bytes,
+            context,
+            columns,
+            suffix,
+        } = *record;
+        match *format {
+            RecordFormat::Csv {
+                delimiter,
+                context: context_hex,
+                entropy,
+            } => {
+                let mut row = vec![
+                    path.to_string(),
+                    (offset + self.base_address).to_string(),
+                    format!("{:08x}", offset + self.base_address),
+                    bytes.len().to_string(),
+                    bytes
+                        .iter()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect::<String>(),
+                ];
+                if context_hex {
+                    row.push(self.format_match("{hex_bytes}", path, offset, context));
+                }
+                // the entropy of the context bytes if any, or else the matched bytes
+                if entropy {
+                    let measured = if context_hex { context } else { bytes };
+                    row.push(format!("{:.2}", bytes_entropy(measured)));
+                }
+                row.extend(columns.iter().map(|column| column.to_string()));
+                csv_row(
+                    &row.iter().map(String::as_str).collect::<Vec<_>>(),
+                    delimiter,
+                )
+            }
+            RecordFormat::Grep => self.format_grep(path, offset, bytes) + suffix,
+            RecordFormat::Template { format, line_width } => {
+                let reported = line_width.map_or(offset, |line_width| offset - offset % line_width);
+                self.format_match(format, path, reported, bytes) + suffix
+            }
+            RecordFormat::Emit(language) => {
+                emit_snippet(language, offset + self.base_address, bytes)
+            }
+        }
+    }
+
+    // Format the offset in the base, padded to the width of the largest offset
+    pub(crate) fn format_offset(&self, offset: usize) -> String {
+        let offset = offset + self.base_address;
+        let width = self.offset_width.get();
+        match self.offset_base {
+            OffsetBase::Hex => format!("{offset:0width$x}"),
+            OffsetBase::Dec => format!("{offset:0width$}"),
+            OffsetBase::Oct => format!("{offset:0width$o}"),
+        }
+    }
+
+    // Widen the offsets to the digits of the largest offset in a file of len bytes,
+    // but not narrower than the digits of the largest 32-bit offset
+    pub(crate) fn fit_offsets(&self, len: usize) {
+        self.offset_width.set(0);
+        let width = self.format_offset(len.saturating_sub(1)).len();
+        self.offset_width
+            .set(width.max(self.offset_base.min_width()));
+    }
+
+    // Print an error message to stderr
+    pub(crate) fn error(&self, message: &str) {
+        if self.quiet < 2 {
+            eprint_line(message);
+        }
+    }
+
+    // Print a note about the search to stderr, which --quiet suppresses along with the output
+    pub(crate) fn note(&self, message: &str) {
+        if self.quiet == 0 {
+            eprint_line(message);
+        }
+    }
+
+    // Write the text to the output, and exit once it fails, e.g.: the pipe is closed by head
+    pub(crate) fn print(&self, text: &str) {
+        self.write(text.as_bytes());
+    }
+
+    // Write the bytes as they are, e.g.: the raw bytes of --extract
+    pub(crate) fn write(&self, bytes: &[u8]) {
+        if let Err(err) = self.out.borrow_mut().write_all(bytes) {
+            if err.kind() != ErrorKind::BrokenPipe {
+                self.error(&format!("Failed to write the output: {err}"));
+            }
+            exit(-1);
+        }
+    }
+
+    pub(crate) fn println(&self, line: &str) {
+        self.print(&format!("{line}\n"));
+    }
+
+    // Write the plain offset of a match to the file of --export-offsets if given,
+    // without padding so that it can be parsed by int(offset, 0) in Python
+    pub(crate) fn export_offset(&self, offset: usize) {
+        let Some((path, file)) = &self.export else {
+            return;
+        };
+        let offset = offset + self.base_address;
+        let line = match self.offset_base {
+            OffsetBase::Hex => format!("0x{offset:x}"),
+            OffsetBase::Dec => format!("{offset}"),
+            OffsetBase::Oct => format!("0o{offset:o}"),
+        };
+        if let Err(err) = writeln!(&*file, "{line}") {
+            self.error(&format!("Failed to write the offsets to {path}: {err}"));
+            exit(-1);
+        }
+    }
+
+    // Print the counts of the byte values which appear, in descending order of the counts
+    pub(crate) fn print_byte_frequency(&self, counts: &[usize; 256]) {
+        let mut frequency = (0..=255u8)
+            .zip(counts.iter().copied())
+            .filter(|(_, count)| *count > 0)
+            .collect::<Vec<_>>();
+        // the stable sort keeps the ascending order of the bytes with the same count
+        frequency.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let width = frequency
+            .first()
+            .map_or(1, |(_, count)| count.to_string().len());
+        self.println("byte frequency:");
+        for row in frequency.chunks(8) {
+            let row = row
+                .iter()
+                .map(|(byte, count)| format!("{byte:02x}: {count:>width$}"))
+                .collect::<Vec<_>>();
+            self.println(&row.join("  "));
+        }
+    }
+
+    // Flush the output before exiting, which skips dropping the buffer
+    pub(crate) fn flush(&self) {
+        if let Err(err) = self.out.borrow_mut().flush() {
+            if err.kind() != ErrorKind::BrokenPipe {
+                self.error(&format!("Failed to write the output: {err}"));
+            }
+            exit(-1);
+        }
+    }
+
+    // Flush the output, and wait for the pager to quit after reading all of it,
+    // so that the messages printed after it don't mess up the screen of the pager
+    pub(crate) fn finish(&self) {
+        self.flush();
+        let mut out = self.out.borrow_mut();
+        if let Writer::Pager(pager) = &mut *out {
+            // the pager reads to the end once its stdin is closed
+            drop(pager.stdin.take());
+            let _ = pager.wait();
+            *out = Writer::Stdout;
+        }
+    }
+
+    // Page the output with the command, which takes the place of stdout
+    pub(crate) fn page(&self, command: &str) -> io::Result<()> {
+        let mut words = command.split_whitespace();
+        let pager = process::Command::new(words.next().unwrap_or_default())
+            .args(words)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        *self.out.borrow_mut() = Writer::Pager(pager);
+        Ok(())
+    }
+
+    // Whether the output is paged, where the progress bar on stderr would mess up the screen
+    pub(crate) fn paging(&self) -> bool {
+        matches!(*self.out.borrow(), Writer::Pager(_))
+    }
+
+    // Print one record, i.e.: the offset line or the line in output formats
+    pub(crate) fn record(&self, record: &str) {
+        if self.null {
+            self.print(&format!("{record}\0"));
+        } else {
+            self.println(record);
+        }
+    }
+
+    // Return the bytes printed, which are empty if there is no byte to print at line_offset
+    pub(crate) fn read_and_print_one_line(
+        &self,
+        source: &mut dyn ReadAt,
+        line_width: usize,
+        line_offset: usize,
+        ranges: &[(Range<usize>, &str)],
+    ) -> Vec<u8> {
+        self.read_and_print_bytes(source, line_width, line_offset, line_width, ranges)
+    }
+
+    // Print only len bytes at line_offset in a line of line_width,
+    // and return the bytes printed, which are empty if there is no byte to print.
+    // The bytes in ranges (relative to line_offset) are highlighted in their styles,
+    // where the first range wins if they overlap.
+    pub(crate) fn read_and_print_bytes(
+        &self,
+        source: &mut dyn ReadAt,
+        line_width: usize,
+        line_offset: usize,
+        len: usize,
+        ranges: &[(Range<usize>, &str)],
+    ) -> Vec<u8> {
+        let mut bytes = vec![0; line_width];
+        let read = source.read_at(line_offset, &mut bytes[..len.min(line_width)]);
+        if read == 0 {
+            return Vec::new();
+        }
+
+        // header
+        let mut line = self.format_offset(line_offset) + self.layout.offset_separator();
+        if self.line_numbers {
+            line += &format!(" {:>1$}", line_offset / line_width, self.offset_width.get());
+        }
+
+        // with --ascii-highlight, the runs of highlighted bytes are marked by > and < in place
+        // of the spaces around them, or inserted around them in the xxd layout without spaces
+        let marked = |i: usize| {
+            self.marker.is_some() && i < read && ranges.iter().any(|(range, _)| range.contains(&i))
+        };
+        let mut line_end_marked = false;
+
+        // hexadecimal bytes
+        let prefix = self.layout.byte_prefix();
+        for (i, byte) in bytes.iter().enumerate().filter(|_| !self.ascii_only) {
+            let opening = marked(i) && !i.checked_sub(1).is_some_and(marked);
+            let closing = !marked(i) && i.checked_sub(1).is_some_and(marked);
+            if prefix.is_empty() && closing {
+                line.push('<');
+            }
+            line += self.layout.group_separator(i, self.group_size);
+            if let Some((_, highlight)) = ranges.iter().find(|(range, _)| range.contains(&i)) {
+                line += highlight;
+            }
+            line += match () {
+                _ if opening => ">",
+                _ if closing && !prefix.is_empty() => "<",
+                _ => prefix,
+            };
+            if i < read {
+                line += &format!("{byte:02x}");
+            } else {
+                // print spaces as place holder
+                line += "  ";
+            }
+            line += &self.style.reset;
+            line_end_marked = marked(i);
+        }
+
+        if self.hex_only {
+            if line_end_marked {
+                line.push('<');
+            }
+            self.println(&line);
+            bytes.truncate(read);
+            return bytes;
+        }
+
+        // chracters, where each highlighted one is preceded by the marker and the last one
+        // in a run is also followed by it, e.g.: |ST*.*.*.*..|
+        let (before, after) = self.layout.gutters();
+        let cells = if self.utf8 {
+            utf8_cells(&bytes[..read])
+        } else {
+            Vec::new()
+        };
+        match before.strip_prefix(' ') {
+            Some(rest) if line_end_marked && !prefix.is_empty() => line += &format!("<{rest}"),
+            _ if line_end_marked => line += &format!("<{before}"),
+            _ => line += before,
+        }
+        for (i, byte) in bytes
+            .iter()
+            .enumerate()
+            .take_while(|(i, _)| *i < read || self.layout.pad_characters())
+        {
+            if let Some((_, highlight)) = ranges.iter().find(|(range, _)| range.contains(&i)) {
+                line += highlight;
+            }
+            if let Some(marker) = self.marker.filter(|_| marked(i)) {
+                line.push(marker);
+            }
+            if i < read {
+                if self.utf8 {
+                    line += &cells[i];
+                } else if byte.is_ascii() && !byte.is_ascii_control() {
+                    line.push(*byte as char);
+                } else {
+                    line.push('.');
+                }
+            } else {
+                line.push(' ');
+            }
+            if let Some(marker) = self.marker.filter(|_| marked(i) && !marked(i + 1)) {
+                line.push(marker);
+            }
+            line += &self.style.reset;
+        }
+        line += after;
+        self.println(&line);
+        bytes.truncate(read);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::DEFAULT_BUFFER_SIZE;
+
+    // Push the matches of lengths[i] bytes at offsets[i] into blocks one by one
+    fn blocks(
+        offsets: &[usize],
+        lengths: &[usize],
+        line_width: usize,
+        context: (usize, usize),
+    ) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        for (i, span) in offsets
+            .iter()
+            .copied()
+            .zip(lengths.iter().copied())
+            .enumerate()
+        {
+            push_block(&mut blocks, i, span, line_width, context);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1536), "1536B");
+        assert_eq!(format_size(DEFAULT_BUFFER_SIZE), "1MiB");
+        assert_eq!(format_size(3 << 30), "3GiB");
+    }
+
+    #[test]
+    fn test_emit_snippet() {
+        assert_eq!(
+            emit_snippet("c", 0x61bd72, &[0x1f, 0x8b, 0x08]),
+            "static const uint8_t data_0x0061bd72[] = {\n    0x1f, 0x8b, 0x08,\n};"
+        );
+        assert_eq!(
+            emit_snippet("rust", 0x61bd72, &[0x1f; 13]),
+            format!(
+                "const DATA_0061BD72: &[u8] = b\"\\\n    {}\\\n    \\x1f\";",
+                "\\x1f".repeat(12)
+            )
+        );
+        assert_eq!(
+            emit_snippet("python", 0x61bd72, b"fox"),
+            "data_0x0061bd72 = (\n    b\"\\x66\\x6f\\x78\"\n)"
+        );
+    }
+
+    #[test]
+    fn test_format_match() {
+        let printer = Printer {
+            offset_width: Cell::new(8),
+            ..Default::default()
+        };
+        assert_eq!(
+            printer.format_offset_line("vmlinuz", 0x0061bd72, 3),
+            "offset: 6405490 (0061bd72), length: 3"
+        );
+        assert_eq!(
+            printer.format_match(
+                "{file}:{offset_dec}:{offset_hex}:{length}:{hex_bytes}:{ascii}",
+                "vmlinuz",
+                16,
+                &[0x1f, 0x41, 0x08]
+            ),
+            "vmlinuz:16:00000010:3:1f 41 08:.A."
+        );
+        // the tokens in the values aren't expanded again, and the unknown ones are kept
+        assert_eq!(
+            printer.format_match("{file}: {hex_bytes} {x}", "{hex_bytes}.bin", 0, &[0xab]),
+            "{hex_bytes}.bin: ab {x}"
+        );
+        assert_eq!(
+            printer.format_match("{entropy}", "vmlinuz", 0, &[0x1f, 0x8b, 0x1f, 0x8b]),
+            "1.00"
+        );
+    }
+
+    #[test]
+    fn test_format_grep() {
+        let printer = Printer::default();
+        assert_eq!(
+            printer.format_grep("vmlinuz", 0x0061bd72, &[0x1f, 0x8b, 0x08]),
+            "vmlinuz:0x0061bd72: 1f 8b 08 |...|"
+        );
+        let line = printer.format_grep("zeros", 0, &[0x41; MAX_GREP_BYTES + 1]);
+        assert!(line.ends_with(" 41 41 ... |AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA|"));
+    }
+
+    #[test]
+    fn test_format_offset() {
+        let mut printer = Printer {
+            offset_base: OffsetBase::Oct,
+            offset_width: Cell::new(OffsetBase::Oct.min_width()),
+            ..Default::default()
+        };
+        assert_eq!(printer.format_offset(8), "00000000010");
+        printer.offset_base = OffsetBase::Dec;
+        printer.offset_width.set(12);
+        assert_eq!(printer.format_offset(16), "000000000016");
+        printer.base_address = 0x08000000;
+        assert_eq!(printer.format_offset(16), "000134217744");
+    }
+
+    #[test]
+    fn test_csv_row() {
+        assert_eq!(csv_row(&["vmlinuz", "16"], ','), "vmlinuz,16");
+        assert_eq!(
+            csv_row(&["a,b", "say \"hi\"", "line\nbreak"], ','),
+            "\"a,b\",\"say \"\"hi\"\"\",\"line\nbreak\""
+        );
+        assert_eq!(csv_row(&["a,b", "c\td"], '\t'), "a,b\t\"c\td\"");
+    }
+
+    #[test]
+    fn test_gap_stats() {
+        assert_eq!(gap_stats(&[16]), None);
+        assert_eq!(gap_stats(&[0, 16, 32, 40, 56]), Some((8, 16, 16)));
+        assert_eq!(gap_stats(&[0, 8, 24]), Some((8, 16, 8)));
+    }
+
+    #[test]
+    fn test_blocks() {
+        // two matches 2 lines apart are merged into one block with 3 lines of context
+        assert_eq!(
+            blocks(&[0x40, 0x60], &[3, 3], 16, (3, 3)),
+            vec![Block {
+                lines: 0x10..0xa0,
+                matches: vec![0, 1]
+            }]
+        );
+        // but not with 0 lines of context
+        assert_eq!(
+            blocks(&[0x40, 0x60], &[3, 3], 16, (0, 0)),
+            vec![
+                Block {
+                    lines: 0x40..0x50,
+                    matches: vec![0]
+                },
+                Block {
+                    lines: 0x60..0x70,
+                    matches: vec![1]
+                }
+            ]
+        );
+        // the match overlaps the line ending
+        assert_eq!(
+            blocks(&[0x4e], &[3], 16, (0, 0)),
+            vec![Block {
+                lines: 0x40..0x60,
+                matches: vec![0]
+            }]
+        );
+        // 1 line before and 3 lines after
+        assert_eq!(
+            blocks(&[0x40], &[3], 16, (1, 3)),
+            vec![Block {
+                lines: 0x30..0x80,
+                matches: vec![0]
+            }]
+        );
+        // the matches ending exactly at the end of a line don't take the next line,
+        // and those spanning two lines take both, given as (offset, length, lines)
+        for (offset, len, lines) in [
+            (0x40, 16, 0x40..0x50),
+            (0x40, 32, 0x40..0x60),
+            (0x44, 12, 0x40..0x50),
+            (0x44, 28, 0x40..0x60),
+            (0x44, 29, 0x40..0x70),
+            (0x4f, 2, 0x40..0x60),
+            (0x44, 0, 0x40..0x50),
+        ] {
+            assert_eq!(
+                blocks(&[offset], &[len], 16, (0, 0)),
+                vec![Block {
+                    lines,
+                    matches: vec![0]
+                }],
+                "{offset:#x}+{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_utf8_cells() {
+        assert_eq!(utf8_cells(b"A\x00"), vec!["A", "."]);
+        // 2 bytes of a character 1 column wide, and 3 bytes of one 2 columns wide
+        assert_eq!(utf8_cells("é中".as_bytes()), vec!["é", " ", "中", " ", ""]);
+        // an invalid byte, a truncated sequence, and a control character (U+0085)
+        assert_eq!(
+            utf8_cells(b"\xff\xc3A\xc2\x85\xe4\xb8"),
+            vec![".", ".", "A", ".", ".", ".", "."]
+        );
+    }
+
+    #[test]
+    fn test_shannon_entropy() {
+        let mut counts = [0; 256];
+        counts[0x41] = 16;
+        assert_eq!(shannon_entropy(&counts), 0.0);
+        counts[0x42] = 16;
+        assert_eq!(shannon_entropy(&counts), 1.0);
+        assert_eq!(shannon_entropy(&[1; 256]), 8.0);
+    }
+}
@@ -0,0 +1,1760 @@
+use clap::{parser::ValueSource, ArgMatches};
+use indicatif::{ProgressBar, ProgressStyle};
+use regex::bytes::Regex;
+#[cfg(feature = "async")]
+use std::collections::HashMap;
+use std::{
+    fs::{self, File},
+    io::{self, IsTerminal, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+#[cfg(feature = "async")]
+use crate::search::search_all_files;
+use crate::{
+    actions::{ExtractLength, Extraction, Replacement},
+    cli::parse_highlight_color,
+    pattern::{parse_replacement, Patterns},
+    printer::{
+        csv_row, eprint_line, format_size, gap_stats, push_block, shannon_entropy, Block,
+        HighlightStyle, Printer, Record, RecordFormat, DUMP_MATCH_FORMAT, HIGHLIGHT_PALETTE,
+        LINE_OFFSET_ONLY_FORMAT, ONLY_MATCHING_FORMAT,
+    },
+    search::{
+        decode_float, decompress, elf_section, file_len, followed_within, literal_bytes,
+        read_bytes, search_each, search_float, search_regex, search_regex_reverse, within_distance,
+        Counted, ReadAt, SearchError, SearchOptions, Section, Windows, DEFAULT_BUFFER_SIZE,
+    },
+};
+
+// The matches of a file found by the search beforehand
+pub(crate) type Found = Result<Vec<Range<usize>>, SearchError>;
+
+// A file opened for the search, which is read through the section of --elf-section
+// unless its decompressed bytes are searched instead
+struct Input<'f> {
+    file: &'f File,
+    reader: Counted<Section<&'f File>>,
+    decoded: Option<Counted<Box<dyn Read + 'f>>>,
+    // pipes, FIFOs, character devices and decompressed bytes can't seek back to print
+    // the matches, so the bytes of context lines around the matches are kept in memory instead
+    seekable: bool,
+    // the bytes of the file, or 0 if unknown
+    len: usize,
+}
+
+// The state of printing the matches of one file, which are printed while searching
+// or after all of them are found
+struct FileOutput<'f> {
+    path: &'f str,
+    // the offset and the length of --elf-section in the file
+    section: Option<(u64, u64)>,
+    separator: Option<&'f String>,
+    // the matches of --then paired with the matches at the offsets, which are known after the search
+    partners: Vec<(usize, Range<usize>)>,
+    count: usize,
+    // the matches found while streaming, including the skipped ones
+    streamed: usize,
+    // the offset and the pattern of the previous match
+    previous: Option<(usize, usize)>,
+    // the offsets of all matches only for the gap statistics
+    gap_offsets: Vec<usize>,
+    // a block is printed once a later match starts another block, or at the end of the file
+    blocks_printed: usize,
+    blocks: Vec<Block>,
+    // the matches of the blocks not printed yet, given as (offset, length, pattern, suffix)
+    records: Vec<(usize, usize, usize, String)>,
+}
+
+impl FileOutput<'_> {
+    // The match of --then paired with the match at the offset
+    fn partner_of(&self, offset: usize) -> Option<Range<usize>> {
+        self.partners
+            .binary_search_by_key(&offset, |(offset, _)| *offset)
+            .ok()
+            .map(|i| self.partners[i].1.clone())
+    }
+}
+
+// Collect the spans of the matches and the windows of a non-seekable input
+fn collect_spans(
+    reader: &mut dyn Read,
+    pattern: &str,
+    pattern_len: usize,
+    options: &SearchOptions,
+) -> Result<(Vec<Range<usize>>, Windows), SearchError> {
+    let mut spans = Vec::new();
+    search_regex(reader, pattern, pattern_len, options, |span, _| {
+        spans.push(span)
+    })
+    .map(|windows| (spans, windows))
+}
+
+// The counters of all files searched for the summary and the exit code
+#[derive(Default)]
+pub(crate) struct Totals {
+    pub(crate) matches: usize,
+    pub(crate) files_searched: usize,
+    pub(crate) files_matched: usize,
+    // the files which fail to open or to be searched
+    pub(crate) files_failed: usize,
+    // the files which fail while searching, besides those failing to open
+    pub(crate) files_unreadable: usize,
+    // the files which can't be patched by --replace or extracted by --extract
+    pub(crate) failed: bool,
+    pub(crate) bytes_scanned: usize,
+    pub(crate) extracted: usize,
+    // the end of the bytes searched in the file of --follow
+    pub(crate) watch_offset: u64,
+    // the file being searched when interrupted and the bytes scanned in it
+    pub(crate) interrupted_at: Option<(String, usize)>,
+}
+
+impl Totals {
+    // Print the summary line, and the statistics of the search with --stats
+    pub(crate) fn print_summary(&self, printer: &Printer, stats: bool, started: Instant) {
+        let Totals {
+            matches: total_matches,
+            files_searched,
+            files_matched,
+            files_failed,
+            bytes_scanned,
+            ..
+        } = *self;
+        if printer.quiet == 0 {
+            // print to stderr so that it doesn't interfere with piped stdout
+            let failed = if files_failed > 0 {
+                format!(", {files_failed} file(s) failed")
+            } else {
+                String::new()
+            };
+            eprint_line(&format!(
+                "--- {total_matches} match(es) in {files_matched} of {files_searched} file(s){failed} ---"
+            ));
+        }
+
+        if stats && printer.quiet < 2 {
+            let elapsed = started.elapsed().as_secs_f64();
+            let megabytes = bytes_scanned as f64 / 1_000_000.0;
+            eprint_line(&format!("files searched: {files_searched}"));
+            eprint_line(&format!("files matched: {files_matched}"));
+            eprint_line(&format!("files failed: {files_failed}"));
+            eprint_line(&format!("total matches: {total_matches}"));
+            eprint_line(&format!(
+                "bytes scanned: {bytes_scanned} ({megabytes:.2} MB)"
+            ));
+            eprint_line(&format!("elapsed time: {elapsed:.3} s"));
+            if elapsed > 0.0 {
+                eprint_line(&format!("throughput: {:.2} MB/s", megabytes / elapsed));
+            }
+        }
+    }
+}
+
+// Search the files for the patterns and print their matches as the arguments ask
+pub(crate) struct Reporter<'a> {
+    matches: &'a ArgMatches,
+    printer: &'a Printer,
+    patterns: &'a Patterns,
+    line_width: usize,
+    regex: bool,
+    unicode: bool,
+    little: bool,
+    // the record printed for each match instead of the hexdump
+    format: Option<RecordFormat<'a>>,
+    // the lines of context on each side, or exactly the bytes of context if any
+    context: (usize, usize),
+    before_bytes: Option<usize>,
+    after_bytes: Option<usize>,
+    // the escape sequences highlighting the matches of each pattern and those of --then
+    highlights: Vec<String>,
+    then_highlight: String,
+    // tag the pattern of each match with its number when it isn't highlighted in color
+    tagged: bool,
+    // the first --skip matches of each file are dropped silently
+    skip: usize,
+    max_count: Option<usize>,
+    align: Option<(usize, usize)>,
+    buffer_size: usize,
+    // set by Ctrl-C to stop the search
+    interrupted: Arc<AtomicBool>,
+    // the output file grows while it's searched, so it's skipped
+    output_path: Option<PathBuf>,
+    reverse: bool,
+    // the offset which the match nearest to is printed
+    near: Option<usize>,
+    within: i64,
+    // a file only matches with all of the patterns
+    all: bool,
+    elf_section_name: Option<&'a String>,
+    extraction: Option<Extraction<'a>>,
+    replacement: Option<Replacement>,
+    // the matches are printed as soon as they are found,
+    // unless all of them are needed first for the arguments
+    streaming: bool,
+    // the engine finding the matches for -v, where memmem finds the fixed byte sequences
+    engine: &'static str,
+}
+
+impl<'a> Reporter<'a> {
+    // Check the arguments of the search, and exit if they don't work together
+    pub(crate) fn new(
+        matches: &'a ArgMatches,
+        printer: &'a Printer,
+        patterns: &'a Patterns,
+        line_width: usize,
+        color: bool,
+        highlight: HighlightStyle,
+        terminal: bool,
+    ) -> Reporter<'a> {
+        let regex = matches.get_flag("regex");
+        let unicode = matches.get_flag("unicode");
+        let little = matches.get_one::<String>("endian").unwrap() == "little";
+        let replacement = matches
+            .get_one::<String>("replace")
+            .map(|replace| Replacement {
+                bytes: parse_replacement(replace, little).unwrap_or_else(|err| {
+                    printer.error(&err);
+                    exit(-1);
+                }),
+                dry_run: matches.get_flag("dry-run"),
+                backup: !matches.get_flag("no-backup"),
+                force: matches.get_flag("force"),
+            });
+        if matches.get_flag("force") && replacement.is_none() && !matches.contains_id("extract") {
+            printer.error("--force only works with --extract or --replace.");
+            exit(-1);
+        }
+        // the matches of the bytes other than --regex have the length of their pattern,
+        // so a replacement of another length is refused before opening any file
+        if let Some(replacement) = replacement.as_ref().filter(|_| !regex) {
+            let bytes = &replacement.bytes;
+            if let Some((_, len)) = patterns.regexes.iter().find(|(_, len)| *len != bytes.len()) {
+                printer.error(&format!(
+                    "The replacement has {} bytes but the pattern has {len} bytes.",
+                    bytes.len()
+                ));
+                exit(-1);
+            }
+        }
+        // within argument is required by near-pattern and then
+        let within = matches
+            .get_one::<i64>("within")
+            .copied()
+            .unwrap_or_default();
+        if matches.contains_id("within")
+            && patterns.near_pattern.is_none()
+            && patterns.then_pattern.is_none()
+        {
+            printer.error("--within only works with --near-pattern or --then.");
+            exit(-1);
+        }
+        if patterns.near_pattern.is_some() && within < 0 {
+            printer.error("--within can't be negative with --near-pattern.");
+            exit(-1);
+        }
+        let all = matches.get_flag("all") && patterns.regexes.len() > 1;
+
+        // -B and -A override the lines of -c on each side
+        let context = matches.get_one::<u8>("context");
+        let lines = |id| *matches.get_one::<u8>(id).or(context).unwrap_or(&0) as usize;
+        let context = (lines("before-context"), lines("after-context"));
+        let before_bytes = matches.get_one::<usize>("before-bytes").copied();
+        let after_bytes = matches.get_one::<usize>("after-bytes").copied();
+
+        // --output-format takes precedence over --only-matching
+        let output_format = matches
+            .get_one::<String>("output-format")
+            .map(String::as_str)
+            .or(matches
+                .get_flag("only-matching")
+                .then_some(ONLY_MATCHING_FORMAT))
+            .or(matches.get_flag("dump-match").then_some(DUMP_MATCH_FORMAT))
+            .or(matches
+                .get_flag("line-offset-only")
+                .then_some(LINE_OFFSET_ONLY_FORMAT));
+        // format argument has default value so it's safe to unwrap
+        let csv = matches.get_one::<String>("format").unwrap() == "csv";
+        let format = match matches.get_one::<String>("format").unwrap().as_str() {
+            "csv" => Some(RecordFormat::Csv {
+                // delimiter argument has default value so it's safe to unwrap
+                delimiter: *matches.get_one::<char>("delimiter").unwrap(),
+                // the context bytes in csv are those which would be dumped around each match
+                context: context != (0, 0) || before_bytes.is_some() || after_bytes.is_some(),
+                entropy: matches.get_flag("show-entropy"),
+            }),
+            "grep" => Some(RecordFormat::Grep),
+            _ => output_format
+                .map(|format| RecordFormat::Template {
+                    format,
+                    // --line-offset-only reports the offset of the line rather than the match
+                    line_width: matches.get_flag("line-offset-only").then_some(line_width),
+                })
+                // the snippets of --emit are never colored
+                .or(matches
+                    .get_one::<String>("emit")
+                    .map(|language| RecordFormat::Emit(language))),
+        };
+
+        // the colors in the palette are valid so it's safe to unwrap
+        let default_palette = HIGHLIGHT_PALETTE
+            .iter()
+            .map(|color| parse_highlight_color(color).unwrap())
+            .collect::<Vec<_>>();
+        let palette = matches.get_one::<Vec<String>>("palette");
+        // each pattern is highlighted in its own color cycling through the palette,
+        // or tagged with its number in the hexdump when colors are disabled
+        let pattern_color = |i: usize| match palette {
+            Some(palette) => highlight.escape(&palette[i % palette.len()]),
+            None if i == 0 => printer.style.highlight.clone(),
+            None => highlight.escape(&default_palette[i % default_palette.len()]),
+        };
+        let highlights = (0..patterns.regexes.len())
+            .map(|i| {
+                if color {
+                    pattern_color(i)
+                } else {
+                    String::new()
+                }
+            })
+            .collect::<Vec<_>>();
+        // the matches of --then are highlighted in the color after those of the patterns
+        let then_highlight = if color {
+            pattern_color(patterns.regexes.len())
+        } else {
+            String::new()
+        };
+
+        // the raw bytes of --extract would mess up the terminal
+        if matches.contains_id("extract") && !matches.contains_id("extract-dir") && terminal {
+            printer.error("Refuse to write the bytes of --extract to the terminal, redirect the output or use --extract-dir.");
+            exit(-1);
+        }
+
+        // --print0 makes no sense in the hexdump
+        if matches.get_flag("print0") && format.is_none() {
+            printer.error("--print0 only works with --only-matching, --dump-match, --output-format, --emit or --format csv or grep.");
+            exit(-1);
+        }
+
+        if !csv
+            && (matches.value_source("delimiter") == Some(ValueSource::CommandLine)
+                || matches.get_flag("no-header"))
+        {
+            printer.error("--delimiter and --no-header only work with --format csv.");
+            exit(-1);
+        }
+
+        let skip = matches
+            .get_one::<usize>("skip")
+            .copied()
+            .unwrap_or_default();
+        // nothing more is needed after the first match following the skipped ones in quiet mode
+        // unless the matches may be dropped by --near-pattern, --then or --all later
+        let max_count = (printer.quiet > 0
+            && patterns.near_pattern.is_none()
+            && patterns.then_search.is_none()
+            && !all)
+            .then_some(skip + 1);
+        let align = matches.get_one::<u64>("align").map(|alignment| {
+            (
+                *alignment as usize,
+                *matches.get_one::<usize>("align-offset").unwrap_or(&0),
+            )
+        });
+        // the overlap around the chunk boundaries must fit in the buffer
+        let pattern_len = patterns.pattern_len;
+        let longest = patterns
+            .near_pattern
+            .as_ref()
+            .or(patterns.then_pattern.as_ref())
+            .map_or(pattern_len, |(_, near_len)| pattern_len.max(*near_len));
+        let buffer_size = matches
+            .get_one::<u64>("buffer-size")
+            .map_or(DEFAULT_BUFFER_SIZE, |size| *size as usize);
+        // the buffer grows with a long pattern like --needle-file unless it's given explicitly,
+        // so that the overlap around the chunk boundaries stays a small part of each read
+        let buffer_size = if matches.contains_id("buffer-size") {
+            buffer_size
+        } else {
+            buffer_size.max(longest.saturating_mul(4))
+        };
+        if buffer_size < longest.max(1) {
+            printer.error(&format!(
+                "The buffer size {buffer_size} is less than the {longest} bytes of the pattern, use --buffer-size {} or larger.",
+                longest.max(1)
+            ));
+            exit(-1);
+        }
+        // Ctrl-C stops the search after the chunk being searched, and then the matches found
+        // so far are printed completely before exiting, which --follow also waits for
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let handler = interrupted.clone();
+        if let Err(err) = ctrlc::set_handler(move || handler.store(true, Ordering::SeqCst)) {
+            printer.error(&format!("Failed to handle Ctrl-C: {err}"));
+            exit(-1);
+        }
+        let output_path = matches
+            .get_one::<String>("output-file")
+            .and_then(|output_file| fs::canonicalize(output_file).ok());
+
+        let reverse = matches.get_flag("reverse-search");
+        let near = matches.get_one::<usize>("near").copied();
+        let extraction = matches
+            .get_one::<ExtractLength>("extract")
+            .map(|length| Extraction {
+                length: *length,
+                // the raw bytes are written to stdout without --extract-dir
+                dir: matches.get_one::<String>("extract-dir").map(Path::new),
+                separator: matches
+                    .get_one::<Vec<u8>>("extract-separator")
+                    .map(Vec::as_slice),
+                // max-extract argument has default value so it's safe to unwrap
+                max_extract: *matches.get_one::<usize>("max-extract").unwrap(),
+                force: matches.get_flag("force"),
+                chunk_size: buffer_size.min(DEFAULT_BUFFER_SIZE),
+            });
+        let streaming = !reverse
+            && !patterns.separate
+            && patterns.float_epsilon.is_none()
+            && patterns.near_pattern.is_none()
+            && near.is_none()
+            && extraction.is_none()
+            && replacement.is_none()
+            && patterns.then_search.is_none()
+            && !all;
+        let engine = if patterns.float.is_some() && patterns.float_epsilon.is_some() {
+            "float"
+        } else if !regex
+            && patterns.then_search.is_none()
+            && (if patterns.separate {
+                patterns
+                    .regexes
+                    .iter()
+                    .map(|(pattern, _)| pattern)
+                    .collect::<Vec<_>>()
+            } else {
+                vec![&patterns.pattern]
+            })
+            .iter()
+            .all(|pattern| literal_bytes(pattern, unicode).is_some())
+        {
+            "memmem"
+        } else {
+            "regex"
+        };
+
+        Reporter {
+            matches,
+            printer,
+            patterns,
+            line_width,
+            regex,
+            unicode,
+            little,
+            tagged: !color && format.is_none(),
+            format,
+            context,
+            before_bytes,
+            after_bytes,
+            highlights,
+            then_highlight,
+            skip,
+            max_count,
+            align,
+            buffer_size,
+            interrupted,
+            output_path,
+            reverse,
+            near,
+            within,
+            all,
+            elf_section_name: matches.get_one::<String>("elf-section"),
+            extraction,
+            replacement,
+            streaming,
+            engine,
+        }
+    }
+
+    // Print the header row of --format csv
+    pub(crate) fn print_csv_header(&self) {
+        let Some(RecordFormat::Csv {
+            delimiter,
+            context,
+            entropy,
+        }) = self.format
+        else {
+            return;
+        };
+        if self.printer.quiet > 0 || self.matches.get_flag("no-header") {
+            return;
+        }
+        let mut header = vec![
+            "file",
+            "offset_dec",
+            "offset_hex",
+            "length",
+            "matched_bytes_hex",
+        ];
+        if context {
+            header.push("context_hex");
+        }
+        if entropy {
+            header.push("entropy");
+        }
+        if self.patterns.regexes.len() > 1 {
+            header.push("pattern");
+        }
+        if self.patterns.labeled {
+            header.push("label");
+        }
+        self.printer.record(&csv_row(&header, delimiter));
+    }
+
+    // Show the progress of the search on stderr only for a human watching it,
+    // where it would mess up the screen of the pager
+    fn show_progress(&self) -> bool {
+        self.printer.quiet == 0 && !self.printer.paging() && io::stderr().is_terminal()
+    }
+
+    // With the async feature, the regular files no larger than the buffer are read
+    // concurrently first, which keeps many reads waiting on a network filesystem at once,
+    // and then their matches are printed one file after another as usual
+    #[cfg(feature = "async")]
+    pub(crate) fn prefetch(&self, paths: &[String]) -> HashMap<String, Found> {
+        let matches = self.matches;
+        let decompress = matches.get_flag("decompress");
+        let gz = |path: &String| path.ends_with(".gz") && !matches.get_flag("no-decompress");
+        let small = paths
+            .iter()
+            .filter(|path| !decompress && !gz(path))
+            .filter(|path| {
+                // the pseudo-files like in /proc report 0 bytes, which are searched as usual
+                fs::metadata(path).is_ok_and(|metadata| {
+                    metadata.is_file() && (1..=self.buffer_size as u64).contains(&metadata.len())
+                })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let options = SearchOptions {
+            max_count: self.max_count,
+            align: self.align,
+            buffer_size: self.buffer_size,
+            unicode: self.unicode,
+            literal: !self.regex,
+            ..Default::default()
+        };
+        match tokio::runtime::Runtime::new() {
+            Ok(runtime) if self.streaming && self.elf_section_name.is_none() && small.len() > 1 => {
+                // nothing is printed until all of them are read, so keep spinning meanwhile
+                let progress = if self.show_progress() {
+                    // the template is valid so it's safe to unwrap
+                    ProgressBar::new_spinner()
+                        .with_style(
+                            ProgressStyle::with_template("Searching {msg}... {spinner}").unwrap(),
+                        )
+                        .with_message(format!("{} files", small.len()))
+                } else {
+                    ProgressBar::hidden()
+                };
+                progress.enable_steady_tick(Duration::from_millis(100));
+                let found = runtime.block_on(search_all_files(
+                    small,
+                    self.patterns.pattern.clone(),
+                    self.patterns.pattern_len,
+                    options,
+                ));
+                progress.finish_and_clear();
+                found
+            }
+            Ok(_) => HashMap::new(),
+            Err(err) => {
+                debug!(%err, "search the files one by one without the runtime");
+                HashMap::new()
+            }
+        }
+    }
+
+    // The number of the i-th pattern, which tags its matches in the hexdump without colors
+    fn tag(&self, i: usize) -> String {
+        if self.tagged {
+            format!("[{}] ", i + 1)
+        } else {
+            String::new()
+        }
+    }
+
+    // The patterns in their highlights, printed after the name of each file
+    fn legend(&self) -> String {
+        let patterns = self.patterns;
+        (0..patterns.regexes.len())
+            .map(|i| {
+                let label = patterns.labels[i]
+                    .as_ref()
+                    .map_or(String::new(), |label| format!("{label}: "));
+                format!(
+                    "{}{}{label}{}{}",
+                    self.highlights[i],
+                    self.tag(i),
+                    patterns.texts[i],
+                    self.printer.style.reset
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // Tell which pattern matches after the offset of each match, by its name if labeled
+    fn pattern_suffix(&self, i: usize) -> String {
+        let patterns = self.patterns;
+        if patterns.labeled {
+            format!(" [{}]", patterns.names[i])
+        } else if patterns.regexes.len() > 1 {
+            format!(" pattern: {}{}", self.tag(i), patterns.texts[i])
+        } else {
+            String::new()
+        }
+    }
+
+    // The pattern and the label of the i-th pattern in the columns of csv
+    fn columns(&self, i: usize) -> Vec<&str> {
+        let patterns = self.patterns;
+        let mut columns = Vec::new();
+        if patterns.regexes.len() > 1 {
+            columns.push(patterns.texts[i].as_str());
+        }
+        if patterns.labeled {
+            columns.push(patterns.names[i].as_str());
+        }
+        columns
+    }
+
+    // The bytes which would be dumped around the match of len bytes at the offset
+    fn context_range(&self, offset: usize, len: usize) -> Range<usize> {
+        let (before_bytes, after_bytes) = (self.before_bytes, self.after_bytes);
+        if before_bytes.is_some() || after_bytes.is_some() {
+            offset.saturating_sub(before_bytes.unwrap_or_default())
+                ..offset + len + after_bytes.unwrap_or_default()
+        } else {
+            let mut blocks = Vec::new();
+            push_block(&mut blocks, 0, (offset, len), self.line_width, self.context);
+            blocks[0].lines.clone()
+        }
+    }
+
+    // Print the record of the match of len bytes at the offset in the format
+    fn print_record(
+        &self,
+        format: &RecordFormat,
+        path: &str,
+        (offset, len): (usize, usize),
+        which: usize,
+        suffix: &str,
+        source: &mut dyn ReadAt,
+    ) {
+        // the snippets of --emit have the bytes of --after-bytes as well
+        let emitted = match format {
+            RecordFormat::Emit(_) => self.after_bytes.unwrap_or_default(),
+            _ => 0,
+        };
+        let bytes = read_bytes(source, offset, len + emitted);
+        let context = match format {
+            RecordFormat::Csv { context: true, .. } => {
+                let range = self.context_range(offset, len);
+                read_bytes(source, range.start, range.len())
+            }
+            _ => Vec::new(),
+        };
+        let printer = self.printer;
+        printer.record(&printer.format_record(
+            format,
+            &Record {
+                path,
+                offset,
+                bytes: &bytes,
+                context: &context,
+                columns: &self.columns(which),
+                suffix,
+            },
+        ));
+    }
+
+    // Pair the matches of the pattern with the matches of --then following them within
+    // the distance, where both may match at an offset, and return the pairs
+    fn pair_then(
+        &self,
+        spans: &[Range<usize>],
+        source: &mut dyn ReadAt,
+    ) -> Vec<(Range<usize>, Range<usize>)> {
+        let Some((_, longest, primary, then)) = &self.patterns.then_search else {
+            return Vec::new();
+        };
+        let (mut primaries, mut others) = (Vec::new(), Vec::new());
+        for span in spans {
+            let bytes = read_bytes(source, span.start, *longest);
+            let found = |re: &Option<Regex>| {
+                re.as_ref()?
+                    .find(&bytes)
+                    .map(|found| span.start..span.start + found.end())
+            };
+            primaries.extend(found(primary));
+            others.extend(found(then));
+        }
+        let starts =
+            |spans: &[Range<usize>]| spans.iter().map(|span| span.start).collect::<Vec<_>>();
+        let pairs = followed_within(&starts(&primaries), &starts(&others), self.within);
+        debug!(
+            "{} of {} matches followed by --then",
+            pairs.len(),
+            primaries.len()
+        );
+        pairs
+            .iter()
+            .map(|(i, j)| (primaries[*i].clone(), others[*j].clone()))
+            .collect()
+    }
+
+    // The first match of each pattern in the order of their offsets, or none unless all of
+    // them match, where more than one pattern may match at the same offset although only
+    // one of them is found by the search
+    fn first_matches(&self, spans: &[Range<usize>], source: &mut dyn ReadAt) -> Vec<Range<usize>> {
+        let patterns = self.patterns;
+        let mut firsts = vec![None; patterns.regexes.len()];
+        for span in spans {
+            let bytes = read_bytes(source, span.start, patterns.pattern_len);
+            for (first, re) in firsts.iter_mut().zip(&patterns.anchored) {
+                if first.is_none() {
+                    *first = re
+                        .as_ref()
+                        .and_then(|re| re.find(&bytes))
+                        .map(|found| span.start..span.start + found.end());
+                }
+            }
+            if firsts.iter().all(Option::is_some) {
+                break;
+            }
+        }
+        debug!("the first matches of the patterns: {firsts:?}");
+        let Some(mut firsts) = firsts.into_iter().collect::<Option<Vec<_>>>() else {
+            return Vec::new();
+        };
+        // the patterns at the same offset stay in their order
+        firsts.sort_by_key(|span| span.start);
+        firsts
+    }
+
+    // Print the name of the file and the legend of the patterns before its matches in the hexdump
+    fn print_header(&self, path: &str, compressed: bool) {
+        let printer = self.printer;
+        if self.format.is_some()
+            || self.replacement.is_some()
+            || self.extraction.is_some()
+            || printer.quiet > 0
+        {
+            return;
+        }
+        let decompressed = if compressed {
+            " (decompressed, compressed offset unknown)"
+        } else {
+            ""
+        };
+        printer.println(&format!(
+            "{}{path}{decompressed}{}:",
+            printer.style.bold, printer.style.reset
+        ));
+        if self.patterns.regexes.len() > 1 {
+            printer.println(&format!("patterns: {}", self.legend()));
+        }
+        printer.println("");
+    }
+
+    // The progress bar of the search in the file of len bytes, or a spinner if it's unknown
+    fn progress_bar(&self, path: &str, len: Option<u64>) -> ProgressBar {
+        if !self.show_progress() {
+            return ProgressBar::hidden();
+        }
+        let (progress, template) = match len {
+            Some(len) => (
+                ProgressBar::new(len),
+                "{msg} [{wide_bar}] {bytes}/{total_bytes} ({eta})",
+            ),
+            None => (ProgressBar::new_spinner(), "{msg} {spinner} {bytes}"),
+        };
+        // the templates are valid so it's safe to unwrap
+        progress
+            .with_style(ProgressStyle::with_template(template).unwrap())
+            .with_message(format!("Searching {path}..."))
+    }
+
+    // The separator between the blocks of matches, where --compact drops the blank line
+    // between the matches without any context like grep, unless another separator is given
+    fn separator(&self) -> Option<&'a String> {
+        let matches = self.matches;
+        // context-separator argument has default value so it's safe to unwrap
+        let separator = matches.get_one::<String>("context-separator").unwrap();
+        Some(separator).filter(|_| {
+            !matches.get_flag("compact")
+                || self.context != (0, 0)
+                || self.before_bytes.is_some()
+                || self.after_bytes.is_some()
+                || matches.value_source("context-separator") != Some(ValueSource::DefaultValue)
+        })
+    }
+
+    // Search one file and print its matches, where found are the matches of the file
+    // if it has been searched beforehand
+    pub(crate) fn search_file(&self, path: &String, found: Option<Found>, totals: &mut Totals) {
+        let &Reporter {
+            matches,
+            printer,
+            patterns,
+            line_width,
+            regex,
+            unicode,
+            format,
+            context,
+            before_bytes,
+            after_bytes,
+            max_count,
+            align,
+            buffer_size,
+            ref interrupted,
+            ref output_path,
+            reverse,
+            near,
+            all,
+            elf_section_name,
+            ref extraction,
+            ref replacement,
+            streaming,
+            engine,
+            ..
+        } = self;
+        let &Patterns {
+            ref pattern,
+            ref original_bytes,
+            separate,
+            float_epsilon,
+            ref near_pattern,
+            ..
+        } = patterns;
+        let verbose = matches.get_count("verbose");
+
+        let file_started = Instant::now();
+        let file = match File::open(path) {
+            Ok(image) => image,
+            Err(err) => {
+                printer.error(&format!("Failed to open file {path}: {err}"));
+                totals.files_failed += 1;
+                return;
+            }
+        };
+        if output_path.is_some() && fs::canonicalize(path).ok() == *output_path {
+            printer.error(&format!("Skip {path} which is the output file."));
+            return;
+        }
+        // the regular files and the block devices have their sizes, which the pipes don't have
+        let size = file_len(&file);
+        // only the files whose size is known, and not the pipes
+        let max_filesize = matches.get_one::<u64>("max-filesize").copied().unwrap_or(0);
+        if let Some(size) = size.filter(|size| max_filesize > 0 && *size > max_filesize) {
+            if printer.quiet == 0 {
+                printer.error(&format!(
+                    "Skip {path} of {size} bytes larger than --max-filesize"
+                ));
+            }
+            return;
+        }
+        totals.files_searched += 1;
+
+        // a seekable device is read at the offsets like a regular file
+        let is_file = size.is_some();
+        let mut decoded = None;
+        let mut compressed = false;
+        // .gz files are decompressed unless --no-decompress is given
+        let gz = path.ends_with(".gz") && !matches.get_flag("no-decompress");
+        if matches.get_flag("decompress") || gz {
+            match decompress(&file) {
+                Ok((stream, is_compressed)) => {
+                    compressed = is_compressed;
+                    // search an uncompressed regular file as usual from the beginning
+                    if compressed || !is_file {
+                        decoded = Some(Counted {
+                            inner: stream,
+                            count: 0,
+                            progress: ProgressBar::hidden(),
+                        });
+                    } else if let Err(err) = (&file).seek(SeekFrom::Start(0)) {
+                        printer.error(&format!("Failed to read file {path}: {err}"));
+                        totals.files_failed += 1;
+                        return;
+                    }
+                }
+                Err(err) => {
+                    printer.error(&format!("Failed to read file {path}: {err}"));
+                    totals.files_failed += 1;
+                    return;
+                }
+            }
+        }
+
+        let seekable = is_file && decoded.is_none();
+        let margins = (!seekable).then_some((
+            (line_width * (context.0 + 1)).max(before_bytes.unwrap_or_default()),
+            (line_width * (context.1 + 1)).max(after_bytes.unwrap_or_default()),
+        ));
+
+        // the length of decompressed bytes is unknown before the search
+        // the pseudo-files like in /proc and /sys can seek but report 0 bytes, so their
+        // length is unknown (0) as well, and the lines are printed till nothing is read
+        let known_size = size.filter(|size| *size > 0);
+        let filelen = if compressed {
+            0
+        } else {
+            known_size.unwrap_or_default() as usize
+        };
+        // the addresses must not overflow, where the decompressed bytes are checked after the search
+        if printer
+            .base_address
+            .checked_add(filelen.saturating_sub(1))
+            .is_none()
+        {
+            printer.error(&format!(
+                "The addresses of {path} exceed 64 bits with --base-address."
+            ));
+            totals.files_failed += 1;
+            return;
+        }
+        printer.fit_offsets(filelen);
+
+        // with --all, the file isn't printed unless it has all of the patterns
+        if !all {
+            self.print_header(path, compressed);
+        }
+        // the file is searched backward or searched again for these arguments,
+        // and the bytes of floats aren't kept in memory
+        let rereading = [
+            (reverse, "--reverse-search"),
+            (near_pattern.is_some(), "--near-pattern"),
+            (separate, "--pattern-file"),
+            (float_epsilon.is_some(), "--float-epsilon"),
+            (replacement.is_some(), "--replace"),
+            (extraction.is_some(), "--extract"),
+            (elf_section_name.is_some(), "--elf-section"),
+        ];
+        if let Some((_, arg)) = rereading.iter().find(|(used, _)| *used && !seekable) {
+            printer.error(&format!("{arg} only works with regular files: {path}"));
+            totals.files_failed += 1;
+            return;
+        }
+        // only the bytes of --elf-section are searched, at the offsets relative to the section
+        let section = match elf_section_name.map(|name| elf_section(&file, name)) {
+            Some(Ok(range)) => Some(range),
+            Some(Err(err)) => {
+                printer.error(&format!("{err}: {path}"));
+                totals.files_failed += 1;
+                return;
+            }
+            None => None,
+        };
+        let (section_start, section_len) = section.unwrap_or((0, u64::MAX));
+        // the length of decompressed bytes is unknown
+        let progress = self.progress_bar(
+            path,
+            (decoded.is_none() && filelen > 0).then_some(section_len.min(filelen as u64)),
+        );
+        // keep spinning while a read is blocked, e.g.: on a slow device or network filesystem,
+        // so that the search doesn't look frozen
+        progress.enable_steady_tick(Duration::from_millis(100));
+        if let Some(decoded) = decoded.as_mut() {
+            decoded.progress = progress.clone();
+        }
+        let mut input = Input {
+            file: &file,
+            reader: Counted {
+                inner: Section {
+                    inner: &file,
+                    start: section_start,
+                    len: section_len,
+                    pos: 0,
+                },
+                count: 0,
+                progress: progress.clone(),
+            },
+            decoded,
+            seekable,
+            len: filelen,
+        };
+        if section.is_some() {
+            if let Err(err) = input.reader.seek(SeekFrom::Start(0)) {
+                printer.error(&format!("Failed to read file {path}: {err}"));
+                totals.files_failed += 1;
+                return;
+            }
+        }
+        let options = SearchOptions {
+            margins,
+            max_count,
+            // keep the alignment to the file offsets rather than the offsets in the section
+            align: align.map(|(alignment, phase)| {
+                let start = section_start as usize % alignment;
+                (
+                    alignment,
+                    (phase % alignment + alignment - start) % alignment,
+                )
+            }),
+            buffer_size,
+            unicode,
+            // a --regex is always searched by the regex engine
+            literal: !regex,
+            interrupted: Some(interrupted.clone()),
+        };
+
+        let mut out = FileOutput {
+            path,
+            section,
+            separator: self.separator(),
+            partners: Vec::new(),
+            count: 0,
+            streamed: 0,
+            previous: None,
+            gap_offsets: Vec::new(),
+            blocks_printed: 0,
+            blocks: Vec::new(),
+            records: Vec::new(),
+        };
+        let found = self.find_matches(&mut input, found, &options, &mut out);
+        progress.finish_and_clear();
+        let Input {
+            reader, decoded, ..
+        } = input;
+        let decoded_len = decoded.map_or(0, |decoded| decoded.count);
+        let scanned = reader.count + decoded_len;
+        totals.bytes_scanned += scanned;
+        if interrupted.load(Ordering::SeqCst) {
+            totals.interrupted_at = Some((path.clone(), scanned));
+        }
+        if verbose > 0 && printer.quiet < 2 {
+            let elapsed = file_started.elapsed().as_secs_f64();
+            let count = match &found {
+                Ok(_) if streaming => out.streamed.saturating_sub(self.skip),
+                Ok((spans, _)) => spans.len(),
+                Err(_) => 0,
+            };
+            eprint_line(&format!(
+                "{path}: scanned {scanned} bytes in {elapsed:.2}s ({:.0} MiB/s), {count} matches, engine={engine}, buffer={}",
+                scanned as f64 / (1 << 20) as f64 / elapsed.max(f64::EPSILON),
+                format_size(buffer_size)
+            ));
+        }
+        totals.watch_offset = reader.count as u64;
+        if printer.base_address.checked_add(decoded_len).is_none() {
+            printer.error(&format!(
+                "The addresses of {path} exceed 64 bits with --base-address."
+            ));
+            totals.files_failed += 1;
+            return;
+        }
+        let (mut spans, windows) = match found {
+            Ok(found) => found,
+            Err(SearchError::InvalidPattern(message)) => {
+                printer.finish();
+                printer.error(&format!(
+                    "Invalid pattern: {original_bytes}\nregex: {pattern}\n{message}"
+                ));
+                exit(2);
+            }
+            Err(SearchError::Io(err)) => {
+                printer.error(&format!("Failed to read {path}: {err}"));
+                totals.files_failed += 1;
+                totals.files_unreadable += 1;
+                return;
+            }
+        };
+        // the matches streamed have been printed already, except the skipped ones
+        let matched = if streaming {
+            out.streamed > self.skip
+        } else {
+            !spans.is_empty()
+        };
+        if !matched {
+            // the rest of the file isn't searched once interrupted
+            if printer.quiet == 0 && totals.interrupted_at.is_none() {
+                let some = if all { "all of " } else { "" };
+                printer.error(&format!(
+                    "Cannot find {some}the bytes in {path}: {original_bytes}\n"
+                ));
+            }
+            return;
+        }
+        totals.files_matched += 1;
+        if printer.quiet > 0 {
+            return;
+        }
+        if all {
+            self.print_header(path, compressed);
+        }
+
+        if let Some(near) = near {
+            // ties are broken toward the lower offset
+            let nearest = spans
+                .iter()
+                .min_by_key(|span| (span.start.abs_diff(near), span.start))
+                .unwrap()
+                .clone();
+            spans = vec![nearest];
+        }
+
+        if self.write_matches(&file, path, &spans, totals) {
+            return;
+        }
+
+        let mut source: Box<dyn ReadAt + '_> = if seekable {
+            Box::new(&file)
+        } else {
+            Box::new(windows)
+        };
+        // print the collected matches, which have been printed while searching otherwise
+        spans
+            .into_iter()
+            .for_each(|span| self.print_match(&mut out, span, source.as_mut()));
+        self.finish_blocks(&mut out, source.as_mut());
+        totals.matches += out.count;
+        if format.is_some() {
+            return;
+        }
+        printer.println("");
+        self.print_footer(&out, if filelen > 0 { filelen } else { scanned });
+    }
+
+    // Search the input for the matches, which are printed as soon as they're found when
+    // streaming, or collected and then narrowed down by the arguments otherwise
+    fn find_matches(
+        &self,
+        input: &mut Input,
+        found: Option<Found>,
+        options: &SearchOptions,
+        out: &mut FileOutput,
+    ) -> Result<(Vec<Range<usize>>, Windows), SearchError> {
+        if self.streaming {
+            return self
+                .stream_matches(input, found, options, out)
+                .map(|windows| (Vec::new(), windows));
+        }
+        let &Patterns {
+            ref regexes,
+            ref pattern,
+            pattern_len,
+            separate,
+            float,
+            float_epsilon,
+            ref then_search,
+            ..
+        } = self.patterns;
+        let reader = &mut input.reader;
+        let (spans, mut windows) = if let Some((pattern, pattern_len, ..)) = then_search {
+            if let Some(decoded) = input.decoded.as_mut() {
+                collect_spans(decoded, pattern, *pattern_len, options)
+            } else {
+                collect_spans(reader, pattern, *pattern_len, options)
+            }
+        } else if let Some(decoded) = input.decoded.as_mut() {
+            collect_spans(decoded, pattern, pattern_len, options)
+        } else if let (Some((width, value)), Some(epsilon)) = (float, float_epsilon) {
+            search_float(reader, width, self.little, (value, epsilon), options)
+                .map(|spans| (spans, Windows::default()))
+        } else if separate {
+            search_each(reader, regexes, options, self.reverse)
+                .map(|spans| (spans, Windows::default()))
+        } else if self.reverse {
+            search_regex_reverse(reader, pattern, pattern_len, options)
+                .map(|spans| (spans, Windows::default()))
+        } else {
+            collect_spans(reader, pattern, pattern_len, options)
+        }?;
+        let start = out.section.map_or(0, |(start, _)| start as usize);
+        let spans = self
+            .near_pattern_matches(&mut input.reader, spans)?
+            .into_iter()
+            .map(|span| span.start + start..span.end + start)
+            .collect();
+        let mut spans = if input.seekable {
+            self.select_matches(spans, &mut input.file, out)
+        } else {
+            self.select_matches(spans, &mut windows, out)
+        };
+        spans.drain(..self.skip.min(spans.len()));
+        Ok((spans, windows))
+    }
+
+    // Print the matches while searching, with the progress bar hidden meanwhile,
+    // where the first --skip matches are dropped
+    fn stream_matches(
+        &self,
+        input: &mut Input,
+        found: Option<Found>,
+        options: &SearchOptions,
+        out: &mut FileOutput,
+    ) -> Result<Windows, SearchError> {
+        let &Patterns {
+            ref pattern,
+            pattern_len,
+            ..
+        } = self.patterns;
+        let (file, seekable) = (input.file, input.seekable);
+        let progress = input.reader.progress.clone();
+        let start = out.section.map_or(0, |(start, _)| start as usize);
+        let mut on_match = |span: Range<usize>, windows: &mut Windows| {
+            out.streamed += 1;
+            if out.streamed <= self.skip {
+                return;
+            }
+            let span = span.start + start..span.end + start;
+            if seekable {
+                progress.suspend(|| self.print_match(out, span, &mut &*file));
+            } else {
+                progress.suspend(|| self.print_match(out, span, windows));
+            }
+        };
+        if let Some(decoded) = input.decoded.as_mut() {
+            search_regex(decoded, pattern, pattern_len, options, on_match)
+        } else if let Some(found) = found {
+            // the file has been read completely by the search
+            input.reader.count = input.len;
+            found.map(|spans| {
+                let mut windows = Windows::default();
+                spans
+                    .into_iter()
+                    .for_each(|span| on_match(span, &mut windows));
+                windows
+            })
+        } else {
+            search_regex(&mut input.reader, pattern, pattern_len, options, on_match)
+        }
+    }
+
+    // Keep the matches with any match of --near-pattern within the distance,
+    // which are searched from the beginning of the file again
+    fn near_pattern_matches(
+        &self,
+        reader: &mut Counted<Section<&File>>,
+        mut spans: Vec<Range<usize>>,
+    ) -> Result<Vec<Range<usize>>, SearchError> {
+        let Some((near_pattern, near_len)) = &self.patterns.near_pattern else {
+            return Ok(spans);
+        };
+        debug!("seek to the beginning for --near-pattern");
+        reader.seek(SeekFrom::Start(0))?;
+        reader.progress.set_position(0);
+        let (others, _) = collect_spans(
+            reader,
+            near_pattern,
+            *near_len,
+            &SearchOptions {
+                buffer_size: self.buffer_size,
+                unicode: self.unicode,
+                literal: !self.regex,
+                ..Default::default()
+            },
+        )?;
+        let starts =
+            |spans: &[Range<usize>]| spans.iter().map(|span| span.start).collect::<Vec<_>>();
+        let near = within_distance(&starts(&spans), &starts(&others), self.within as usize);
+        spans.retain(|span| near.binary_search(&span.start).is_ok());
+        Ok(spans)
+    }
+
+    // Keep the matches followed by the matches of --then, which are paired with them for
+    // printing, and only the first match of each pattern with --all
+    fn select_matches(
+        &self,
+        spans: Vec<Range<usize>>,
+        source: &mut dyn ReadAt,
+        out: &mut FileOutput,
+    ) -> Vec<Range<usize>> {
+        let spans = if self.patterns.then_search.is_some() {
+            // tell the matches of the pattern from those of --then, where both may match at an offset
+            let pairs = self.pair_then(&spans, source);
+            out.partners = pairs
+                .iter()
+                .map(|(span, partner)| (span.start, partner.clone()))
+                .collect();
+            pairs.into_iter().map(|(span, _)| span).collect()
+        } else {
+            spans
+        };
+        if self.all {
+            self.first_matches(&spans, source)
+        } else {
+            spans
+        }
+    }
+
+    // Extract the matches with --extract or patch them with --replace instead of printing them,
+    // and return whether either of them is given
+    fn write_matches(
+        &self,
+        file: &File,
+        path: &str,
+        spans: &[Range<usize>],
+        totals: &mut Totals,
+    ) -> bool {
+        let printer = self.printer;
+        if self.extraction.is_none() && self.replacement.is_none() {
+            return false;
+        }
+        totals.matches += spans.len();
+        spans
+            .iter()
+            .for_each(|span| printer.export_offset(span.start));
+        let written = match &self.extraction {
+            Some(extraction) => {
+                extraction.extract(printer, file, path, spans, &mut totals.extracted)
+            }
+            None => self
+                .replacement
+                .as_ref()
+                .is_some_and(|replacement| replacement.replace(printer, file, path, spans)),
+        };
+        if !written {
+            totals.failed = true;
+        }
+        true
+    }
+
+    // Which pattern matches at the offset, where the next matching pattern is taken
+    // for the offset of the previous match
+    fn which_pattern(
+        &self,
+        offset: usize,
+        previous: Option<(usize, usize)>,
+        source: &mut dyn ReadAt,
+    ) -> usize {
+        let patterns = self.patterns;
+        if patterns.regexes.len() == 1 {
+            return 0;
+        }
+        let skip = match previous {
+            Some((last, which)) if last == offset => which + 1,
+            _ => 0,
+        };
+        let bytes = read_bytes(source, offset, patterns.pattern_len);
+        patterns
+            .anchored
+            .iter()
+            .skip(skip)
+            .position(|re| re.as_ref().is_some_and(|re| re.is_match(&bytes)))
+            .map_or(0, |position| skip + position)
+    }
+
+    // The details appended to the offset of the match of the pattern at the offset
+    fn match_suffix(
+        &self,
+        out: &FileOutput,
+        offset: usize,
+        which: usize,
+        source: &mut dyn ReadAt,
+    ) -> String {
+        let printer = self.printer;
+        let mut suffix = String::new();
+        if let (Some(name), Some((start, _))) = (self.elf_section_name, out.section) {
+            let delta = offset - start as usize;
+            suffix += &format!(" section: {name}+0x{delta:x}");
+        }
+        // the float actually found at the offset
+        if let Some((width, _)) = self.patterns.float {
+            if let Some(float) = decode_float(&read_bytes(source, offset, width), self.little) {
+                let value = if width == 4 {
+                    (float as f32).to_string()
+                } else {
+                    float.to_string()
+                };
+                suffix += &format!(" value: {value}");
+            }
+        }
+        suffix += &self.pattern_suffix(which);
+        if let Some(partner) = out.partner_of(offset) {
+            suffix += &format!(
+                " then: {} ({})",
+                partner.start + printer.base_address,
+                printer.format_offset(partner.start)
+            );
+        }
+        // the offset relative to the previous match (or 0 for the first one)
+        if self.matches.get_flag("relative-offsets") {
+            let delta = offset - out.previous.map_or(0, |(last, _)| last);
+            suffix += &format!(" (+0x{delta:04x})");
+        }
+        if let Some((last, _)) = out.previous.filter(|_| self.matches.get_flag("show-gaps")) {
+            let gap = offset - last;
+            suffix += &format!(" gap: {gap} (0x{gap:x})");
+        }
+        suffix
+    }
+
+    // Print the match at the span, as a record in the format or in the hexdump, where the
+    // matches in the hexdump are kept in blocks until a later match starts another block
+    fn print_match(&self, out: &mut FileOutput, span: Range<usize>, source: &mut dyn ReadAt) {
+        out.count += 1;
+        self.printer.export_offset(span.start);
+        if self.printer.quiet > 0 {
+            return;
+        }
+        let (offset, len) = (span.start, span.len());
+        let which = self.which_pattern(offset, out.previous, source);
+        let suffix = self.match_suffix(out, offset, which, source);
+        out.previous = Some((offset, which));
+        if self.matches.get_flag("show-gaps") {
+            out.gap_offsets.push(offset);
+        }
+
+        if let Some(format) = &self.format {
+            self.print_record(format, out.path, (offset, len), which, &suffix, source);
+            source.drop_before(offset);
+            return;
+        }
+        if self.before_bytes.is_some() || self.after_bytes.is_some() {
+            self.print_context_bytes(out, (offset, len), which, &suffix, source);
+            return;
+        }
+
+        push_block(
+            &mut out.blocks,
+            out.count - 1,
+            (offset, len),
+            self.line_width,
+            self.context,
+        );
+        out.records.push((offset, len, which, suffix));
+        // the previous block is complete since this match starts another one
+        if out.blocks.len() > 1 {
+            let block = out.blocks.remove(0);
+            let end = block.lines.end;
+            self.print_block(out, &block, source);
+            source.drop_before(end);
+        }
+    }
+
+    // Print the blocks left after the last match
+    fn finish_blocks(&self, out: &mut FileOutput, source: &mut dyn ReadAt) {
+        for block in std::mem::take(&mut out.blocks) {
+            self.print_block(out, &block, source);
+        }
+    }
+
+    // Dump exactly the context bytes around the match of len bytes at the offset,
+    // starting at the first context byte rather than the line boundary
+    fn print_context_bytes(
+        &self,
+        out: &FileOutput,
+        (offset, len): (usize, usize),
+        which: usize,
+        suffix: &str,
+        source: &mut dyn ReadAt,
+    ) {
+        let printer = self.printer;
+        let line_width = self.line_width;
+        if let Some(separator) = out.separator.filter(|_| out.count > 1) {
+            printer.println(separator);
+        }
+        printer.record(&(printer.format_offset_line(out.path, offset, len) + suffix));
+        let start = offset.saturating_sub(self.before_bytes.unwrap_or_default());
+        let end = offset + len + self.after_bytes.unwrap_or_default();
+        let mut counts = [0; 256];
+        for line_offset in (start..end).step_by(line_width) {
+            // the range of the match in this line
+            let range = Range {
+                start: offset.saturating_sub(line_offset),
+                end: (offset + len).saturating_sub(line_offset),
+            };
+            let bytes = printer.read_and_print_bytes(
+                source,
+                line_width,
+                line_offset,
+                end - line_offset,
+                &[(range, &self.highlights[which])],
+            );
+            if bytes.is_empty() {
+                printer.println("(EOF)");
+                break;
+            }
+            bytes.iter().for_each(|byte| counts[*byte as usize] += 1);
+        }
+        self.print_counts(&counts);
+        source.drop_before(start);
+    }
+
+    // Print the next block with the offset lines of its matches and the lines of the block
+    fn print_block(&self, out: &mut FileOutput, block: &Block, source: &mut dyn ReadAt) {
+        let printer = self.printer;
+        let line_width = self.line_width;
+        let records = out.records.drain(..block.matches.len()).collect::<Vec<_>>();
+        if let Some(separator) = out.separator.filter(|_| out.blocks_printed > 0) {
+            printer.println(separator);
+        }
+        out.blocks_printed += 1;
+        records.iter().for_each(|(offset, len, _, suffix)| {
+            printer.record(&(printer.format_offset_line(out.path, *offset, *len) + suffix));
+            if let Some(near) = self.near {
+                let distance = *offset as i128 - near as i128;
+                printer.println(&format!(
+                    "distance: {distance:+} from {} ({})",
+                    near + printer.base_address,
+                    printer.format_offset(near)
+                ));
+            }
+        });
+        let occurrences = self.occurrences(&block.lines, source);
+        let mut counts = [0; 256];
+        for line_offset in block.lines.clone().step_by(line_width) {
+            // the ranges of all matches in this line
+            let ranges = records
+                .iter()
+                .map(|(offset, len, which, _)| {
+                    let range = Range {
+                        start: offset.saturating_sub(line_offset),
+                        end: (offset + len).saturating_sub(line_offset),
+                    };
+                    (range, self.highlights[*which].as_str())
+                })
+                // the match of --then is highlighted as well if it's in the line
+                .chain(records.iter().filter_map(|(offset, _, _, _)| {
+                    let partner = out.partner_of(*offset)?;
+                    let range = Range {
+                        start: partner.start.saturating_sub(line_offset),
+                        end: partner.end.saturating_sub(line_offset),
+                    };
+                    Some((range, self.then_highlight.as_str()))
+                }))
+                // after the matches, which take precedence
+                .chain(occurrences.iter().map(|(occurrence, which)| {
+                    let range = Range {
+                        start: occurrence.start.saturating_sub(line_offset),
+                        end: occurrence.end.saturating_sub(line_offset),
+                    };
+                    (range, self.highlights[*which].as_str())
+                }))
+                .collect::<Vec<_>>();
+            let bytes = printer.read_and_print_one_line(source, line_width, line_offset, &ranges);
+            // only after-context lines can be beyond the end of file
+            if bytes.is_empty() {
+                printer.println("(EOF)");
+                break;
+            }
+            bytes.iter().for_each(|byte| counts[*byte as usize] += 1);
+        }
+        self.print_counts(&counts);
+    }
+
+    // With --highlight-all, every occurrence of the patterns overlapping the lines,
+    // searched from one byte before them so that \A only matches at the start of file,
+    // and to one byte after them so that \z only matches at the end of file
+    fn occurrences(
+        &self,
+        lines: &Range<usize>,
+        source: &mut dyn ReadAt,
+    ) -> Vec<(Range<usize>, usize)> {
+        let &Patterns {
+            ref occurring,
+            pattern_len,
+            ..
+        } = self.patterns;
+        let mut occurrences = Vec::new();
+        if occurring.is_empty() {
+            return occurrences;
+        }
+        let start = lines.start.saturating_sub(pattern_len);
+        let wanted = lines.end + pattern_len + 1 - start;
+        let bytes = read_bytes(source, start, wanted);
+        for (which, re) in occurring.iter().enumerate() {
+            let mut at = usize::from(start > 0);
+            while let Some(m) = re.find_at(&bytes, at) {
+                let beyond = bytes.len() == wanted && m.end() == wanted;
+                if !m.range().is_empty() && !beyond {
+                    occurrences.push((start + m.start()..start + m.end(), which));
+                }
+                at = m.start() + 1;
+                if at > bytes.len() {
+                    break;
+                }
+            }
+        }
+        occurrences
+    }
+
+    // Print the frequency or the entropy of the bytes counted in the hexdump of a match
+    // and its context
+    fn print_counts(&self, counts: &[usize; 256]) {
+        let printer = self.printer;
+        if self.matches.get_flag("byte-frequency") {
+            printer.print_byte_frequency(counts);
+        }
+        if self.matches.get_flag("show-entropy") {
+            printer.println(&format!(
+                "entropy: {:.2} bits/byte",
+                shannon_entropy(counts)
+            ));
+        }
+    }
+
+    // Print the statistics of the gaps between the matches with --show-gaps, and the footer
+    // of the file of size bytes with --file-footer
+    fn print_footer(&self, out: &FileOutput, size: usize) {
+        let printer = self.printer;
+        let show_gaps = self.matches.get_flag("show-gaps");
+        if let Some((min, max, common)) = gap_stats(&out.gap_offsets).filter(|_| show_gaps) {
+            printer.println(&format!(
+                "gaps: min {min} (0x{min:x}), max {max} (0x{max:x}), most common {common} (0x{common:x})\n"
+            ));
+        }
+        // tell the files with similar names apart, where the size of a pipe or
+        // a compressed file is the bytes searched
+        if self.matches.get_flag("file-footer") {
+            printer.flush();
+            printer.note(&format!(
+                "--- {} match(es) found in {} ({size} bytes) ---",
+                out.count, out.path
+            ));
+        }
+    }
+
+    // Keep searching the bytes appended to the file until interrupted, like tail -F
+    pub(crate) fn follow(&self, path: &String, totals: &mut Totals) {
+        let &Reporter {
+            matches,
+            printer,
+            patterns,
+            line_width,
+            regex,
+            unicode,
+            format,
+            ref highlights,
+            buffer_size,
+            ref interrupted,
+            ..
+        } = self;
+        let &Patterns {
+            ref pattern,
+            pattern_len,
+            ref anchored,
+            ..
+        } = patterns;
+        // poll-interval argument has default value so it's safe to unwrap
+        let poll_interval =
+            Duration::from_millis(*matches.get_one::<u64>("poll-interval").unwrap());
+        while !interrupted.load(Ordering::SeqCst) {
+            printer.flush();
+            thread::sleep(poll_interval);
+            // the file may be rotated and created again
+            let Ok(file) = File::open(path) else {
+                continue;
+            };
+            let len = file.metadata().map_or(0, |metadata| metadata.len());
+            // search the file again from the beginning once it's truncated
+            if len < totals.watch_offset {
+                printer.flush();
+                printer.error(&format!(
+                    "{path} is truncated, search it again from the beginning."
+                ));
+                totals.watch_offset = 0;
+            }
+            if len == totals.watch_offset {
+                continue;
+            }
+            // overlap the bytes before the last end of file in case a match spans it
+            let start = totals
+                .watch_offset
+                .saturating_sub(pattern_len.saturating_sub(1) as u64);
+            let mut reader = Counted {
+                inner: Section {
+                    inner: &file,
+                    start,
+                    len: u64::MAX,
+                    pos: 0,
+                },
+                count: 0,
+                progress: ProgressBar::hidden(),
+            };
+            let mut spans = Vec::new();
+            let found = reader
+                .seek(SeekFrom::Start(0))
+                .map_err(SearchError::from)
+                .and_then(|_| {
+                    search_regex(
+                        &mut reader,
+                        pattern,
+                        pattern_len,
+                        &SearchOptions {
+                            buffer_size,
+                            unicode,
+                            literal: !regex,
+                            ..Default::default()
+                        },
+                        |span, _| spans.push(span),
+                    )
+                });
+            let searched = totals.watch_offset;
+            totals.watch_offset = start + reader.count as u64;
+            totals.bytes_scanned += reader.count;
+            if found.is_err() {
+                continue;
+            }
+            // the matches which end before the last end of file have been printed
+            let spans = spans
+                .iter()
+                .map(|span| span.start + start as usize..span.end + start as usize)
+                .filter(|span| span.end as u64 > searched)
+                .collect::<Vec<_>>();
+            if !spans.is_empty() {
+                totals.files_matched = totals.files_matched.max(1);
+            }
+            totals.matches += spans.len();
+            spans.iter().for_each(|span| {
+                let offset = span.start;
+                printer.export_offset(offset);
+                let bytes = read_bytes(&mut &file, offset, span.len());
+                let which = anchored
+                    .iter()
+                    .position(|re| re.as_ref().is_some_and(|re| re.is_match(&bytes)))
+                    .unwrap_or(0);
+                let suffix = self.pattern_suffix(which);
+                if let Some(format) = &format {
+                    self.print_record(
+                        format,
+                        path,
+                        (offset, span.len()),
+                        which,
+                        &suffix,
+                        &mut &file,
+                    );
+                    return;
+                }
+                printer.record(&(printer.format_offset_line(path, offset, bytes.len()) + &suffix));
+                let first_line = offset - offset % line_width;
+                for line_offset in (first_line..span.end.max(offset + 1)).step_by(line_width) {
+                    let range = Range {
+                        start: offset.saturating_sub(line_offset),
+                        end: span.end.saturating_sub(line_offset),
+                    };
+                    printer.read_and_print_one_line(
+                        &mut &file,
+                        line_width,
+                        line_offset,
+                        &[(range, &highlights[which])],
+                    );
+                }
+                printer.println("");
+            });
+        }
+    }
+}
@@ -0,0 +1,1446 @@
+use flate2::read::MultiGzDecoder;
+use indicatif::ProgressBar;
+use memchr::memmem::Finder;
+use object::{
+    read::elf::{ElfFile32, ElfFile64},
+    Endianness, FileKind, Object, ObjectSection, ReadCache,
+};
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind, Literal};
+#[cfg(feature = "async")]
+use std::{collections::HashMap, path::Path};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tracing::debug;
+use xz2::read::XzDecoder;
+
+// Keep the offsets which have any of the other (sorted) offsets within distance bytes
+pub(crate) fn within_distance(offsets: &[usize], others: &[usize], distance: usize) -> Vec<usize> {
+    offsets
+        .iter()
+        .filter(|offset| {
+            // the first one of the others which isn't too far before the offset
+            let i = others.partition_point(|other| other + distance < **offset);
+            others
+                .get(i)
+                .is_some_and(|other| other.abs_diff(**offset) <= distance)
+        })
+        .copied()
+        .collect()
+}
+
+// Pair the offsets with the first of the other (sorted) offsets within distance bytes after them,
+// or with the last one within -distance bytes before them if distance is negative,
+// returning the indices of the paired offsets and others
+pub(crate) fn followed_within(
+    offsets: &[usize],
+    others: &[usize],
+    distance: i64,
+) -> Vec<(usize, usize)> {
+    let reach = distance.unsigned_abs() as usize;
+    offsets
+        .iter()
+        .enumerate()
+        .filter_map(|(i, offset)| {
+            let j = if distance >= 0 {
+                let j = others.partition_point(|other| other < offset);
+                others.get(j).filter(|other| **other - offset <= reach)?;
+                j
+            } else {
+                let j = others
+                    .partition_point(|other| other <= offset)
+                    .checked_sub(1)?;
+                (offset - others[j] <= reach).then_some(j)?
+            };
+            Some((i, j))
+        })
+        .collect()
+}
+
+// Return whether every match of the regex starts at the start of input (\A or ^),
+// and whether any match of it ends at the end of input (\z or $)
+pub(crate) fn regex_anchors(pattern: &str, unicode: bool) -> (bool, bool) {
+    regex_syntax::ParserBuilder::new()
+        .unicode(unicode)
+        .allow_invalid_utf8(true)
+        .build()
+        .parse(pattern)
+        .map_or((false, false), |hir| {
+            (hir.is_anchored_start(), hir.is_any_anchored_end())
+        })
+}
+
+// Read bytes at an offset of the input, which is either a seekable file
+// or the bytes kept in memory around the matches of a non-seekable input
+pub(crate) trait ReadAt {
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> usize;
+
+    // Drop the bytes before offset which won't be read any more
+    fn drop_before(&mut self, _offset: usize) {}
+}
+
+// The position of the file is restored after reading,
+// so that the matches can be printed while the file is searched
+impl ReadAt for &File {
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> usize {
+        let Ok(position) = self.stream_position() else {
+            return 0;
+        };
+        if self.seek(SeekFrom::Start(offset as u64)).is_err() {
+            return 0;
+        }
+        let mut read = 0;
+        while read < buf.len() {
+            match self.read(&mut buf[read..]) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => read += len,
+            }
+        }
+        if self.seek(SeekFrom::Start(position)).is_err() {
+            return 0;
+        }
+        read
+    }
+}
+
+// Bytes kept in memory around the matches of a non-seekable input,
+// as pairs of (offset, bytes) in the order of offsets
+#[derive(Default)]
+pub(crate) struct Windows(Vec<(usize, Vec<u8>)>);
+
+impl ReadAt for Windows {
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> usize {
+        self.0
+            .iter()
+            .find(|(start, bytes)| (*start..start + bytes.len()).contains(&offset))
+            .map_or(0, |(start, bytes)| {
+                let bytes = &bytes[offset - start..];
+                let read = bytes.len().min(buf.len());
+                buf[..read].copy_from_slice(&bytes[..read]);
+                read
+            })
+    }
+
+    fn drop_before(&mut self, offset: usize) {
+        self.0.retain(|(start, bytes)| start + bytes.len() > offset);
+    }
+}
+
+// The default size of the buffer to read the input
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+// Options of search_regex() besides the pattern
+pub(crate) struct SearchOptions {
+    // when margins of (before, after) bytes are given, the bytes from before the start
+    // until after the end of each match are kept in memory, so that a non-seekable
+    // input doesn't need to be read again to print the matches
+    pub(crate) margins: Option<(usize, usize)>,
+    // stop the search after finding max_count matches
+    pub(crate) max_count: Option<usize>,
+    // only find the matches at offsets where (offset - phase) % alignment == 0,
+    // given as (alignment, phase)
+    pub(crate) align: Option<(usize, usize)>,
+    // the bytes read at a time, which must not be less than the bytes of a match
+    pub(crate) buffer_size: usize,
+    // enable Unicode in the regex, which only matches valid UTF-8 for the classes like \p{L}
+    pub(crate) unicode: bool,
+    // find the pattern without the regex engine if it's a fixed byte sequence
+    pub(crate) literal: bool,
+    // set by Ctrl-C to stop the search after the chunk being searched,
+    // which keeps the matches found so far
+    pub(crate) interrupted: Option<Arc<AtomicBool>>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            margins: None,
+            max_count: None,
+            align: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            unicode: false,
+            literal: true,
+            interrupted: None,
+        }
+    }
+}
+
+impl SearchOptions {
+    pub(crate) fn interrupted(&self) -> bool {
+        self.interrupted
+            .as_ref()
+            .is_some_and(|interrupted| interrupted.load(Ordering::SeqCst))
+    }
+}
+
+// Return the bytes of the pattern if it only matches a fixed non-empty byte sequence
+pub(crate) fn literal_bytes(pattern: &str, unicode: bool) -> Option<Vec<u8>> {
+    // decode the long escaped bytes like --needle-file without building the HIR of each byte
+    if !unicode && pattern.len() >= 4 && pattern.len().is_multiple_of(4) {
+        let bytes = pattern
+            .as_bytes()
+            .chunks(4)
+            .map(|escaped| {
+                let hex = std::str::from_utf8(escaped.strip_prefix(br"\x")?).ok()?;
+                hex.chars()
+                    .all(|c| c.is_ascii_hexdigit())
+                    .then(|| u8::from_str_radix(hex, 16).ok())?
+            })
+            .collect::<Option<Vec<_>>>();
+        if bytes.is_some() {
+            return bytes;
+        }
+    }
+    fn push_literal(hir: &Hir, bytes: &mut Vec<u8>) -> bool {
+        match hir.kind() {
+            HirKind::Literal(Literal::Byte(byte)) => bytes.push(*byte),
+            HirKind::Literal(Literal::Unicode(char)) => {
+                bytes.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes())
+            }
+            HirKind::Group(group) => return push_literal(&group.hir, bytes),
+            HirKind::Concat(hirs) => return hirs.iter().all(|hir| push_literal(hir, bytes)),
+            _ => return false,
+        }
+        true
+    }
+    let hir = regex_syntax::ParserBuilder::new()
+        .unicode(unicode)
+        .allow_invalid_utf8(true)
+        .build()
+        .parse(pattern)
+        .ok()?;
+    let mut bytes = Vec::new();
+    (push_literal(&hir, &mut bytes) && !bytes.is_empty()).then_some(bytes)
+}
+
+// The failures of a search, where finding no match isn't one of them
+#[derive(Debug)]
+pub(crate) enum SearchError {
+    // the message of the regex engine about the pattern
+    InvalidPattern(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for SearchError {
+    fn from(err: io::Error) -> Self {
+        SearchError::Io(err)
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::InvalidPattern(message) => write!(f, "{message}"),
+            SearchError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+// The engine to find the matches of a pattern
+pub(crate) enum Matcher {
+    Regex(Regex),
+    // memmem is much faster than the regex engine for a fixed byte sequence
+    Literal(Box<Finder<'static>>),
+}
+
+impl Matcher {
+    pub(crate) fn new(pattern: &str, options: &SearchOptions) -> Result<Matcher, SearchError> {
+        if options.literal {
+            if let Some(bytes) = literal_bytes(pattern, options.unicode) {
+                return Ok(Matcher::Literal(Box::new(Finder::new(&bytes).into_owned())));
+            }
+        }
+        // Disable Unicode (\u flag) to search arbitrary (non-UTF-8) bytes unless it's enabled
+        RegexBuilder::new(pattern)
+            .unicode(options.unicode)
+            .build()
+            .map(Matcher::Regex)
+            .map_err(|err| SearchError::InvalidPattern(err.to_string()))
+    }
+
+    // Return the span of the leftmost match starting from at
+    pub(crate) fn find_at(&self, haystack: &[u8], at: usize) -> Option<Range<usize>> {
+        match self {
+            Matcher::Regex(re) => re.find_at(haystack, at).map(|m| m.range()),
+            Matcher::Literal(finder) => finder
+                .find(&haystack[at..])
+                .map(|start| at + start..at + start + finder.needle().len()),
+        }
+    }
+}
+
+// pattern_len is the number of bytes a match spans, which is used to
+// overlap the search around the chunk boundaries.
+// on_match is called with the span of each match in ascending order as soon as it's
+// found, or once the bytes around it are kept in the windows given margins, so that
+// the matches are printed while searching. Return the windows which are still kept.
+pub(crate) fn search_regex<R: Read>(
+    mut reader: R,
+    pattern: &str,
+    pattern_len: usize,
+    options: &SearchOptions,
+    mut on_match: impl FnMut(Range<usize>, &mut Windows),
+) -> Result<Windows, SearchError> {
+    let mut chunk = vec![0; options.buffer_size];
+    let matcher = Matcher::new(pattern, options)?;
+    debug!(
+        pattern,
+        pattern_len,
+        literal = matches!(matcher, Matcher::Literal(_)),
+        "search forward"
+    );
+    // \z matches at the end of each chunk, which is only the end of input once nothing
+    // more is read, and nothing after the start of input matches \A
+    let (anchored_start, anchored_end) = regex_anchors(pattern, options.unicode);
+    // a match of a variable length may go on in the next chunk, e.g.: A{3,10} or A+,
+    // while a fixed byte sequence never does
+    let variable = anchored_end || matches!(matcher, Matcher::Regex(_));
+
+    // bytes read from the input but not dropped yet, starting at offset data_start
+    let mut data = Vec::new();
+    let mut data_start = 0;
+    // matches are only searched from this offset to avoid reporting them twice
+    let mut searched = 0;
+    let mut found = 0;
+    // the matches waiting for their windows to be read completely
+    let mut unreported = Vec::new();
+    let mut pending: Vec<Range<usize>> = Vec::new();
+    let mut windows = Windows::default();
+    let (before, after) = options.margins.unwrap_or_default();
+    loop {
+        // the start of the match held back at the end of the bytes read
+        let mut held = None;
+        let read = match reader.read(&mut chunk) {
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        debug!(start = data_start + data.len(), read, "read a chunk");
+        data.extend_from_slice(&chunk[..read]);
+        let data_end = data_start + data.len();
+        // the bytes read are the last ones at the end of input or once interrupted
+        let last = read == 0 || options.interrupted();
+
+        // find all non-overlapping matches
+        let mut at = searched - data_start;
+        while options.max_count.is_none_or(|max| found < max) && at <= data.len() {
+            let Some(m) = matcher.find_at(&data, at) else {
+                break;
+            };
+            // search the match again with the next chunk, which may extend it
+            if variable && !last && m.end == data.len() {
+                held = Some(data_start + m.start);
+                break;
+            }
+            let offset = data_start + m.start;
+            debug!(offset, end = data_start + m.end, "found a match");
+            // search again from the next byte of an unaligned match
+            // in case an aligned match overlaps it
+            if options
+                .align
+                .is_some_and(|(alignment, phase)| offset % alignment != phase % alignment)
+            {
+                at = m.start + 1;
+                continue;
+            }
+            found += 1;
+            let span = offset..data_start + m.end;
+            searched = data_start + m.end;
+            // move on at least one byte after an empty match
+            at = if m.start == m.end { m.end + 1 } else { m.end };
+            if options.margins.is_some() {
+                let window = offset.saturating_sub(before)..offset + pattern_len + after;
+                match pending.last_mut() {
+                    Some(last) if last.end >= window.start => last.end = window.end,
+                    _ => pending.push(window),
+                }
+                unreported.push(span);
+            } else {
+                on_match(span, &mut windows);
+            }
+        }
+        // overlap the search around the chunk boundaries
+        // in case the pattern locates across the boundary
+        // where a match at the end of the chunk is searched again
+        let overlap = if anchored_end {
+            data_end.saturating_sub(pattern_len)
+        } else {
+            data_end.saturating_sub(pattern_len.saturating_sub(1))
+        };
+        if !last && overlap > searched {
+            debug!(
+                from = overlap,
+                to = data_end,
+                "rewind around the chunk boundary"
+            );
+        }
+        searched = searched.max(overlap).min(held.unwrap_or(usize::MAX));
+
+        // keep the windows which have been read completely (or till the end of input)
+        pending.retain(|window| {
+            if !last && window.end > data_end {
+                return true;
+            }
+            let end = window.end.min(data_end);
+            match windows.0.last_mut() {
+                // merge with the last window so that a line never spans two windows
+                Some((start, bytes)) if *start + bytes.len() >= window.start => {
+                    let last_end = *start + bytes.len();
+                    if end > last_end {
+                        bytes.extend_from_slice(&data[last_end - data_start..end - data_start]);
+                    }
+                }
+                _ => windows.0.push((
+                    window.start,
+                    data[window.start - data_start..end - data_start].to_vec(),
+                )),
+            }
+            false
+        });
+        // report the matches whose windows aren't pending any more
+        let kept = pending.first().map_or(usize::MAX, |window| window.start);
+        let ready = unreported.partition_point(|span: &Range<usize>| span.start < kept);
+        unreported
+            .drain(..ready)
+            .for_each(|span| on_match(span, &mut windows));
+
+        if last {
+            if read != 0 {
+                debug!(end = data_end, "interrupted");
+            }
+            break;
+        }
+        // stop reading once enough matches have been found and kept,
+        // or once the start of input has been searched for a pattern anchored to it
+        let done = options.max_count.is_some_and(|max| found >= max)
+            || (anchored_start && !anchored_end && searched > 0);
+        if done && pending.is_empty() {
+            break;
+        }
+
+        // drop the bytes which are needed neither by the search nor by the windows,
+        // but keep at least one byte before the bytes to search, which is never the start
+        // of haystack where \A matches
+        let keep_from = pending
+            .iter()
+            .map(|window| window.start)
+            .fold(searched.saturating_sub(before.max(1)), usize::min)
+            .max(data_start);
+        data.drain(..keep_from - data_start);
+        data_start = keep_from;
+    }
+
+    Ok(windows)
+}
+
+// Find the matches starting in start..end of a seekable input of len bytes, by searching
+// forward from start in chunks like search_regex() does, given that no match starts in
+// the pattern_len - 1 bytes before end
+fn search_range<R: Read + Seek>(
+    reader: &mut R,
+    matcher: &Matcher,
+    (start, end, len): (usize, usize, usize),
+    pattern_len: usize,
+    anchored_end: bool,
+    options: &SearchOptions,
+    mut on_match: impl FnMut(Range<usize>),
+) -> io::Result<()> {
+    let mut at = start;
+    let mut from = start;
+    while from < end && !options.interrupted() {
+        let to = (from + options.buffer_size).min(end);
+        // read one byte before the chunk, which is never the start of haystack where \A
+        // matches, and one byte more than a match starting in the chunk may span,
+        // which is never the end of haystack where \z matches unless it's the end of input
+        let first = from.saturating_sub(1);
+        let last = (to + pattern_len).min(len);
+        let mut data = vec![0; last - first];
+        debug!(start = first, read = data.len(), "seek and read a chunk");
+        reader.seek(SeekFrom::Start(first as u64))?;
+        reader.read_exact(&mut data)?;
+
+        while at < to {
+            let Some(m) = matcher.find_at(&data, at - first) else {
+                break;
+            };
+            let offset = first + m.start;
+            if offset >= to {
+                break;
+            }
+            // \z matches at the end of the bytes read, which isn't the end of input
+            if anchored_end && m.end == data.len() && last < len {
+                at = offset + 1;
+                continue;
+            }
+            debug!(offset, end = first + m.end, "found a match");
+            if options
+                .align
+                .is_some_and(|(alignment, phase)| offset % alignment != phase % alignment)
+            {
+                at = offset + 1;
+                continue;
+            }
+            on_match(offset..first + m.end);
+            // move on at least one byte after an empty match
+            at = if m.start == m.end {
+                first + m.end + 1
+            } else {
+                first + m.end
+            };
+        }
+        // nothing starts in the rest of the chunk
+        at = at.max(to);
+        from = to;
+    }
+    Ok(())
+}
+
+// Search the chunks from the end of a seekable input toward the beginning,
+// so that max_count matches nearest to the end are found without reading the
+// whole input, and return the spans of matches in ascending order, which are
+// the same matches as the forward search finds
+pub(crate) fn search_regex_reverse<R: Read + Seek>(
+    mut reader: R,
+    pattern: &str,
+    pattern_len: usize,
+    options: &SearchOptions,
+) -> Result<Vec<Range<usize>>, SearchError> {
+    let matcher = Matcher::new(pattern, options)?;
+    debug!(
+        pattern,
+        pattern_len,
+        literal = matches!(matcher, Matcher::Literal(_)),
+        "search backward"
+    );
+    let (_, anchored_end) = regex_anchors(pattern, options.unicode);
+
+    let len = reader.seek(SeekFrom::End(0))? as usize;
+    // the spans of matches from the end, where the ones in each chunk are in ascending order
+    let mut spans = Vec::new();
+    let mut end = len;
+    while end > 0 && options.max_count.is_none_or(|max| spans.len() < max) && !options.interrupted()
+    {
+        // a match found in the chunk may overlap the matches of the forward search
+        // once a match starts in the bytes before the chunk and goes on in it,
+        // so step back until no match starts there, where the search gets in step
+        // with the forward search regardless of the matches before
+        let mut start = end.saturating_sub(options.buffer_size);
+        while start > 0 {
+            let before = start.saturating_sub(pattern_len.saturating_sub(1));
+            let mut overlapping = false;
+            search_range(
+                &mut reader,
+                &matcher,
+                (before, start, len),
+                pattern_len,
+                anchored_end,
+                options,
+                |_| overlapping = true,
+            )?;
+            if !overlapping {
+                break;
+            }
+            debug!(
+                start,
+                "step back from a match going on across the chunk boundary"
+            );
+            start = start.saturating_sub(options.buffer_size);
+        }
+
+        // only the last matches are needed with max_count
+        let wanted = options
+            .max_count
+            .map_or(usize::MAX, |max| max - spans.len());
+        let mut found = VecDeque::new();
+        search_range(
+            &mut reader,
+            &matcher,
+            (start, end, len),
+            pattern_len,
+            anchored_end,
+            options,
+            |span| {
+                if found.len() == wanted {
+                    found.pop_front();
+                }
+                found.push_back(span);
+            },
+        )?;
+        spans.extend(found.into_iter().rev());
+        end = start;
+    }
+
+    spans.reverse();
+    Ok(spans)
+}
+
+// Return the file offset and the size of the named section of an ELF file
+pub(crate) fn elf_section(file: &File, name: &str) -> Result<(u64, u64), String> {
+    fn file_range<'data>(object: &impl Object<'data>, name: &str) -> Result<(u64, u64), String> {
+        let section = object
+            .section_by_name(name)
+            .ok_or_else(|| format!("There is no section {name}"))?;
+        // e.g.: .bss takes no bytes in the file
+        section
+            .file_range()
+            .ok_or_else(|| format!("The section {name} has no bytes in the file"))
+    }
+    let cache = ReadCache::new(file);
+    match FileKind::parse(&cache) {
+        Ok(FileKind::Elf32) => file_range(
+            &ElfFile32::<Endianness, _>::parse(&cache).map_err(|err| err.to_string())?,
+            name,
+        ),
+        Ok(FileKind::Elf64) => file_range(
+            &ElfFile64::<Endianness, _>::parse(&cache).map_err(|err| err.to_string())?,
+            name,
+        ),
+        _ => Err(String::from("It isn't an ELF file")),
+    }
+}
+
+// Restrict the reads and the seeks to len bytes from start of the inner reader,
+// where the offsets are relative to start
+pub(crate) struct Section<R> {
+    pub(crate) inner: R,
+    pub(crate) start: u64,
+    pub(crate) len: u64,
+    pub(crate) pos: u64,
+}
+
+impl<R: Read> Read for Section<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.len.saturating_sub(self.pos).min(buf.len() as u64) as usize;
+        let read = self.inner.read(&mut buf[..len])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Section<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            // the section may be given longer than the rest of the input, e.g.: the whole input
+            SeekFrom::End(delta) => {
+                let end = self.inner.seek(SeekFrom::End(0))?;
+                let len = self.len.min(end.saturating_sub(self.start));
+                len.checked_add_signed(delta)
+            }
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::from(ErrorKind::InvalidInput))?;
+        self.inner.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+// Return the length of a seekable input by seeking to its end and back to the beginning,
+// e.g.: a block device whose metadata reports 0 bytes, or None for a stream like a pipe,
+// which can't seek, or like /dev/zero, which seeks to 0 but never ends
+pub(crate) fn seekable_len<S: Seek>(mut input: S) -> Option<u64> {
+    let len = input.seek(SeekFrom::End(0)).ok()?;
+    input.seek(SeekFrom::Start(0)).ok()?;
+    (len > 0).then_some(len)
+}
+
+// Return the length of a regular file or a seekable device, or None for the other inputs
+pub(crate) fn file_len(file: &File) -> Option<u64> {
+    match file.metadata() {
+        Ok(metadata) if metadata.is_file() => Some(metadata.len()),
+        _ => seekable_len(file),
+    }
+}
+
+// Detect gzip or xz by the magic bytes, and return the stream of the decompressed
+// bytes (or the input itself if it isn't compressed) and whether it's compressed
+pub(crate) fn decompress(file: &File) -> io::Result<(Box<dyn Read + '_>, bool)> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+    let mut magic = Vec::new();
+    file.take(XZ_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    let gzip = magic.starts_with(&GZIP_MAGIC);
+    let xz = magic.starts_with(&XZ_MAGIC);
+    // put the magic bytes back in front of the input, which may not be seekable
+    let input = io::Cursor::new(magic).chain(file);
+    Ok(if gzip {
+        (Box::new(MultiGzDecoder::new(input)), true)
+    } else if xz {
+        (Box::new(XzDecoder::new_multi_decoder(input)), true)
+    } else {
+        (Box::new(input), false)
+    })
+}
+
+// Search each of the patterns separately from the beginning of a seekable input,
+// and return the spans of all their matches in ascending order, where an offset
+// is repeated in the order of patterns if more than one pattern match at it
+pub(crate) fn search_each<R: Read + Seek>(
+    mut reader: R,
+    patterns: &[(String, usize)],
+    options: &SearchOptions,
+    reverse: bool,
+) -> Result<Vec<Range<usize>>, SearchError> {
+    let mut spans = Vec::new();
+    for (pattern, pattern_len) in patterns {
+        debug!("seek to the beginning for the next pattern");
+        reader.seek(SeekFrom::Start(0))?;
+        if reverse {
+            spans.extend(search_regex_reverse(
+                &mut reader,
+                pattern,
+                *pattern_len,
+                options,
+            )?);
+        } else {
+            search_regex(&mut reader, pattern, *pattern_len, options, |span, _| {
+                spans.push(span)
+            })?;
+        }
+    }
+    // the stable sort keeps the order of patterns at the same offset
+    spans.sort_by_key(|span| span.start);
+    Ok(spans)
+}
+
+// The number of files searched at the same time by search_all_files()
+#[cfg(feature = "async")]
+pub(crate) const ASYNC_FILES: usize = 64;
+
+// Read a regular file with the asynchronous I/O of tokio and return the spans
+// of the matches, which are the same as search_regex() finds in the file.
+// The whole file is kept in memory, so it's meant for the small files
+#[cfg(feature = "async")]
+pub(crate) async fn search_file_async(
+    path: &Path,
+    pattern: &str,
+    pattern_len: usize,
+    options: &SearchOptions,
+) -> Result<Vec<Range<usize>>, SearchError> {
+    use tokio::io::AsyncReadExt;
+
+    let mut bytes = Vec::new();
+    tokio::fs::File::open(path)
+        .await?
+        .read_to_end(&mut bytes)
+        .await?;
+    let mut spans = Vec::new();
+    search_regex(
+        bytes.as_slice(),
+        pattern,
+        pattern_len,
+        options,
+        |span, _| spans.push(span),
+    )?;
+    Ok(spans)
+}
+
+// Search the files concurrently with at most ASYNC_FILES of them read at a time,
+// and return the spans of the matches or the error of each file
+#[cfg(feature = "async")]
+pub(crate) async fn search_all_files(
+    paths: Vec<String>,
+    pattern: String,
+    pattern_len: usize,
+    options: SearchOptions,
+) -> HashMap<String, Result<Vec<Range<usize>>, SearchError>> {
+    let (pattern, options) = (Arc::new(pattern), Arc::new(options));
+    let permits = Arc::new(tokio::sync::Semaphore::new(ASYNC_FILES));
+    let mut tasks = tokio::task::JoinSet::new();
+    for path in paths {
+        let (pattern, options, permits) = (pattern.clone(), options.clone(), permits.clone());
+        tasks.spawn(async move {
+            // the semaphore is never closed so it's safe to unwrap
+            let _permit = permits.acquire().await.unwrap();
+            let found = search_file_async(Path::new(&path), &pattern, pattern_len, &options).await;
+            (path, found)
+        });
+    }
+    let mut found = HashMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        // the file of a failed task is searched again as usual
+        if let Ok((path, result)) = joined {
+            found.insert(path, result);
+        }
+    }
+    found
+}
+
+// Decode the bytes of an f32 or f64 in the endianness
+pub(crate) fn decode_float(bytes: &[u8], little: bool) -> Option<f64> {
+    match bytes.len() {
+        4 => {
+            let bytes = bytes.try_into().ok()?;
+            Some(if little {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            } as f64)
+        }
+        8 => {
+            let bytes = bytes.try_into().ok()?;
+            Some(if little {
+                f64::from_le_bytes(bytes)
+            } else {
+                f64::from_be_bytes(bytes)
+            })
+        }
+        _ => None,
+    }
+}
+
+// Find the floats of width bytes within epsilon of the value by decoding the bytes
+// at every offset, which can't be done by a regex
+pub(crate) fn search_float<R: Read>(
+    mut reader: R,
+    width: usize,
+    little: bool,
+    (value, epsilon): (f64, f64),
+    options: &SearchOptions,
+) -> Result<Vec<Range<usize>>, SearchError> {
+    let mut chunk = vec![0; options.buffer_size];
+    // bytes read from the input but not decoded yet, starting at offset data_start
+    let mut data = Vec::new();
+    let mut data_start = 0;
+    let mut spans = Vec::new();
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk[..read]);
+
+        let decoded = data.len().saturating_sub(width - 1);
+        for (i, bytes) in data.windows(width).enumerate() {
+            if options.max_count.is_some_and(|max| spans.len() >= max) {
+                break;
+            }
+            let offset = data_start + i;
+            if options
+                .align
+                .is_some_and(|(alignment, phase)| offset % alignment != phase % alignment)
+            {
+                continue;
+            }
+            if decode_float(bytes, little).is_some_and(|float| (float - value).abs() <= epsilon) {
+                spans.push(offset..offset + width);
+            }
+        }
+        // keep the bytes of the float which may locate across the chunk boundary
+        data.drain(..decoded);
+        data_start += decoded;
+        // the rest of the input isn't read once max_count matches are found
+        if options.interrupted() || options.max_count.is_some_and(|max| spans.len() >= max) {
+            break;
+        }
+    }
+
+    Ok(spans)
+}
+
+// Count the bytes read from the inner reader, and advance the progress bar with them
+pub(crate) struct Counted<R> {
+    pub(crate) inner: R,
+    pub(crate) count: usize,
+    pub(crate) progress: ProgressBar,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read;
+        self.progress.inc(read as u64);
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Counted<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+pub(crate) fn read_bytes(source: &mut dyn ReadAt, offset: usize, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    let read = source.read_at(offset, &mut bytes[..]);
+    bytes.truncate(read);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::{push_block, Block};
+
+    // Collect the spans of the matches found by search_regex() and the windows kept by it
+    fn collect<R: Read>(
+        reader: R,
+        pattern: &str,
+        pattern_len: usize,
+        options: &SearchOptions,
+    ) -> Result<(Vec<Range<usize>>, Windows), SearchError> {
+        let mut spans = Vec::new();
+        search_regex(reader, pattern, pattern_len, options, |span, _| {
+            spans.push(span)
+        })
+        .map(|windows| (spans, windows))
+    }
+
+    // The searches run by both the blocking and the asynchronous I/O
+    #[cfg(feature = "async")]
+    fn search_cases() -> Vec<(&'static str, usize, SearchOptions)> {
+        vec![
+            (r"\x1f\x8b\x08", 3, SearchOptions::default()),
+            (
+                r"\x1f\x8b\x08",
+                3,
+                SearchOptions {
+                    buffer_size: 3,
+                    ..Default::default()
+                },
+            ),
+            (
+                r"\x00+\xff",
+                4096,
+                SearchOptions {
+                    literal: false,
+                    ..Default::default()
+                },
+            ),
+            (
+                r"fox",
+                3,
+                SearchOptions {
+                    max_count: Some(2),
+                    ..Default::default()
+                },
+            ),
+            (
+                r"\x00\x00",
+                2,
+                SearchOptions {
+                    align: Some((16, 8)),
+                    ..Default::default()
+                },
+            ),
+            (r"(", 1, SearchOptions::default()),
+        ]
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_search_file_async() {
+        let path = "tests/data/vmlinuz-6.4-x86_64";
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        for (pattern, pattern_len, options) in search_cases() {
+            let file = File::open(path).unwrap();
+            let expected = collect(&file, pattern, pattern_len, &options).map(|(spans, _)| spans);
+            let found = runtime.block_on(search_file_async(
+                Path::new(path),
+                pattern,
+                pattern_len,
+                &options,
+            ));
+            assert_eq!(format!("{found:?}"), format!("{expected:?}"), "{pattern}");
+        }
+        let found = runtime.block_on(search_all_files(
+            vec![path.to_string(), String::from("tests/data/nonexistent")],
+            String::from(r"\x1f\x8b\x08"),
+            3,
+            SearchOptions::default(),
+        ));
+        assert_eq!(found[path].as_ref().unwrap().len(), 3);
+        assert!(matches!(
+            found["tests/data/nonexistent"],
+            Err(SearchError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_collect() {
+        let file = File::open("tests/data/vmlinuz-6.4-x86_64").unwrap();
+        let (spans, _) = collect(&file, r"\x1f\x8b\x08", 3, &SearchOptions::default()).unwrap();
+        assert_eq!(
+            spans,
+            vec![
+                0x0061bd72..0x0061bd75,
+                0x006b7b9e..0x006b7ba1,
+                0x0085ab9f..0x0085aba2
+            ]
+        );
+
+        let file = File::open("tests/data/vmlinuz-6.4-x86_64").unwrap();
+        let (spans, _) = collect(
+            &file,
+            r"\x1f\x8b\x08",
+            3,
+            &SearchOptions {
+                max_count: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(spans, vec![0x0061bd72..0x0061bd75]);
+    }
+
+    #[test]
+    fn test_search_anchored() {
+        assert_eq!(regex_anchors(r"\A\x4d\x5a", false), (true, false));
+        assert_eq!(regex_anchors(r"^\x4d|\x5a$", false), (false, true));
+        assert_eq!(regex_anchors(r"\x4d\x5a", false), (false, false));
+
+        // the anchors match the start and the end of input rather than those of each chunk
+        let bytes = b"abcabcabc";
+        for buffer_size in [3, 4, 5, 64] {
+            let options = SearchOptions {
+                buffer_size,
+                ..Default::default()
+            };
+            let spans = |pattern| collect(&bytes[..], pattern, 3, &options).unwrap().0;
+            assert_eq!(spans(r"\Aabc"), vec![0..3], "{buffer_size}");
+            assert!(spans(r"\A(?:bc|ca)").is_empty(), "{buffer_size}");
+            assert_eq!(spans(r"abc\z"), vec![6..9], "{buffer_size}");
+            assert_eq!(
+                spans(r"c\z|a"),
+                vec![0..1, 3..4, 6..7, 8..9],
+                "{buffer_size}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_variable_length() {
+        // a match of a variable length isn't split at the chunk boundaries
+        let bytes = [b"x".repeat(12), b"A".repeat(10)].concat();
+        for buffer_size in [10, 16, 64] {
+            let options = SearchOptions {
+                buffer_size,
+                ..Default::default()
+            };
+            let spans = |pattern| collect(&bytes[..], pattern, 10, &options).unwrap().0;
+            assert_eq!(spans("A{3,10}"), vec![12..22], "{buffer_size}");
+            assert_eq!(spans("A+"), vec![12..22], "{buffer_size}");
+        }
+    }
+
+    #[test]
+    fn test_search_literal() {
+        assert_eq!(
+            literal_bytes(r"\x1f\x8b\x08", false),
+            Some(vec![0x1f, 0x8b, 0x08])
+        );
+        assert_eq!(literal_bytes(r"\x1f(?s:.)\x08", false), None);
+        assert_eq!(literal_bytes(r"(?:\x1f)|(?:\x8b)", false), None);
+
+        // pseudo-random bytes with plenty of matches of the short patterns
+        let mut state = 0x2545f491u32;
+        let random: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % 4) as u8
+            })
+            .collect();
+        let vmlinuz = std::fs::read("tests/data/vmlinuz-6.4-x86_64").unwrap();
+        for (data, pattern) in [
+            (&vmlinuz, r"\x1f\x8b\x08"),
+            (&vmlinuz, r"\x00\x00\x00\x00"),
+            (&random, r"\x00\x01\x02"),
+            (&random, r"\x03\x03"),
+        ] {
+            let pattern_len = literal_bytes(pattern, false).unwrap().len();
+            let search = |literal, reverse| {
+                let options = SearchOptions {
+                    buffer_size: 1024,
+                    literal,
+                    ..Default::default()
+                };
+                if reverse {
+                    search_regex_reverse(io::Cursor::new(data), pattern, pattern_len, &options)
+                        .unwrap()
+                } else {
+                    collect(io::Cursor::new(data), pattern, pattern_len, &options)
+                        .unwrap()
+                        .0
+                }
+            };
+            for reverse in [false, true] {
+                assert_eq!(search(true, reverse), search(false, reverse));
+            }
+        }
+    }
+
+    #[test]
+    fn test_section() {
+        let data = (0..=255).collect::<Vec<u8>>();
+        let mut section = Section {
+            inner: io::Cursor::new(&data),
+            start: 0x10,
+            len: 0x20,
+            pos: 0,
+        };
+        section.seek(SeekFrom::Start(0)).unwrap();
+        let (spans, _) = collect(&mut section, r"|/0|", 2, &Default::default()).unwrap();
+        assert_eq!(spans, vec![0x08..0x09]);
+        let spans = search_regex_reverse(&mut section, r"./", 2, &Default::default());
+        assert_eq!(spans.unwrap(), vec![0x1e..0x20]);
+
+        assert!(elf_section(&File::open("tests/data/fox.txt.xz").unwrap(), ".text").is_err());
+    }
+
+    #[test]
+    fn test_counted() {
+        // the progress bar advances with the bytes read through it even when hidden
+        let mut reader = Counted {
+            inner: io::Cursor::new([0; 100]),
+            count: 0,
+            progress: ProgressBar::hidden(),
+        };
+        let mut buf = [0; 64];
+        assert_eq!(reader.read(&mut buf).unwrap(), 64);
+        assert_eq!(reader.read(&mut buf).unwrap(), 36);
+        assert_eq!(reader.count, 100);
+        assert_eq!(reader.progress.position(), 100);
+    }
+
+    #[test]
+    fn test_search_regex_reverse() {
+        let file = File::open("tests/data/vmlinuz-6.4-x86_64").unwrap();
+        let spans =
+            search_regex_reverse(&file, r"\x1f\x8b\x08", 3, &SearchOptions::default()).unwrap();
+        assert_eq!(
+            spans,
+            vec![
+                0x0061bd72..0x0061bd75,
+                0x006b7b9e..0x006b7ba1,
+                0x0085ab9f..0x0085aba2
+            ]
+        );
+
+        let spans = search_regex_reverse(
+            &file,
+            r"\x1f\x8b\x08",
+            3,
+            &SearchOptions {
+                max_count: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(spans, vec![0x006b7b9e..0x006b7ba1, 0x0085ab9f..0x0085aba2]);
+
+        // across the boundary of the last two chunks of 1024 bytes
+        let mut data = vec![0; 4096];
+        data[3071..3074].copy_from_slice(&[0x1f, 0x8b, 0x08]);
+        let spans = search_regex_reverse(
+            io::Cursor::new(data),
+            r"\x1f\x8b\x08",
+            3,
+            &SearchOptions {
+                buffer_size: 1024,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(spans, vec![3071..3074]);
+    }
+
+    #[test]
+    fn test_search_regex_chunk_boundaries() {
+        // the matches end at, span and start right before the chunk boundaries,
+        // which are searched again in the overlap of the next chunk
+        let mut bytes = vec![0; 4096];
+        let expected = vec![1021..1024, 2046..2049, 3070..3073];
+        for span in &expected {
+            bytes[span.clone()].copy_from_slice(&[0x1f, 0x8b, 0x08]);
+        }
+        for buffer_size in [3, 5, 1024] {
+            let options = SearchOptions {
+                buffer_size,
+                ..Default::default()
+            };
+            for pattern in [r"\x1f\x8b\x08", r"\x1f(?s:.)\x08"] {
+                // each offset is reported exactly once in ascending order
+                let (spans, _) = collect(&bytes[..], pattern, 3, &options).unwrap();
+                assert_eq!(spans, expected);
+                let spans =
+                    search_regex_reverse(io::Cursor::new(&bytes), pattern, 3, &options).unwrap();
+                assert_eq!(spans, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_regex_reverse_overlapping() {
+        // the self-overlapping patterns match the same bytes backward as forward
+        // regardless of the chunk boundaries
+        let mut mixed = vec![0x03; 16];
+        mixed[3] = 0;
+        mixed[10] = 0;
+        for bytes in [vec![0; 16], mixed] {
+            for buffer_size in [3, 5, 7] {
+                for literal in [true, false] {
+                    let options = SearchOptions {
+                        buffer_size,
+                        literal,
+                        ..Default::default()
+                    };
+                    for pattern in [r"\x00\x00", r"\x03\x03"] {
+                        let (forward, _) = collect(&bytes[..], pattern, 2, &options).unwrap();
+                        let backward =
+                            search_regex_reverse(io::Cursor::new(&bytes), pattern, 2, &options)
+                                .unwrap();
+                        assert_eq!(backward, forward, "{pattern} {buffer_size}");
+                        // the last ones of the same matches with max_count
+                        let last = search_regex_reverse(
+                            io::Cursor::new(&bytes),
+                            pattern,
+                            2,
+                            &SearchOptions {
+                                max_count: Some(2),
+                                buffer_size,
+                                literal,
+                                ..Default::default()
+                            },
+                        )
+                        .unwrap();
+                        assert_eq!(last, forward[forward.len().saturating_sub(2)..]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_regex_reverse_anchored() {
+        // the anchors only match at the start and the end of input, not of each chunk
+        for buffer_size in [1, 2, 4] {
+            let options = SearchOptions {
+                buffer_size,
+                ..Default::default()
+            };
+            let spans = |pattern| {
+                search_regex_reverse(io::Cursor::new(b"cacaca"), pattern, 2, &options).unwrap()
+            };
+            assert_eq!(spans(r"a\z"), vec![5..6], "{buffer_size}");
+            assert_eq!(spans(r"(?-m)a$"), vec![5..6], "{buffer_size}");
+            assert_eq!(spans(r"\Ac"), vec![0..1], "{buffer_size}");
+            assert_eq!(spans(r"(?-m)^c"), vec![0..1], "{buffer_size}");
+        }
+    }
+
+    #[test]
+    fn test_search_regex_variable_length() {
+        let bytes = b"\x00\x1f\x8b\x08\x00\x1f\x8b\x00\x00\x1f\x8b\x08\x08";
+        let (spans, _) =
+            collect(&bytes[..], r"\x1f\x8b\x08*", 4, &SearchOptions::default()).unwrap();
+        // the highlighted bytes follow the actual length of each match
+        assert_eq!(spans, vec![1..4, 5..7, 9..13]);
+        let mut blocks = Vec::new();
+        for (i, span) in spans.iter().enumerate() {
+            push_block(&mut blocks, i, (span.start, span.len()), 4, (0, 0));
+        }
+        assert_eq!(
+            blocks,
+            vec![Block {
+                lines: 0..16,
+                matches: vec![0, 1, 2]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_search_regex_streaming() {
+        // count the bytes read from the input so far
+        struct Tracked<'a> {
+            bytes: &'a [u8],
+            read: &'a std::cell::Cell<usize>,
+        }
+        impl Read for Tracked<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let read = self.bytes.read(buf)?;
+                self.read.set(self.read.get() + read);
+                Ok(read)
+            }
+        }
+        let mut bytes = vec![0; 8192];
+        bytes[100..103].copy_from_slice(&[0x1f, 0x8b, 0x08]);
+        bytes[5000..5003].copy_from_slice(&[0x1f, 0x8b, 0x08]);
+        for margins in [None, Some((16, 16))] {
+            let read = std::cell::Cell::new(0);
+            let mut reported = Vec::new();
+            search_regex(
+                Tracked {
+                    bytes: &bytes,
+                    read: &read,
+                },
+                r"\x1f\x8b\x08",
+                3,
+                &SearchOptions {
+                    margins,
+                    buffer_size: 1024,
+                    ..Default::default()
+                },
+                |span, _| reported.push((span, read.get())),
+            )
+            .unwrap();
+            // each match is reported once the chunk having it and its margins is read
+            assert_eq!(reported, vec![(100..103, 1024), (5000..5003, 5120)]);
+        }
+    }
+
+    #[test]
+    fn test_search_regex_windows() {
+        // the pattern locates across the boundary of the first chunk
+        let mut bytes = vec![0; 2048];
+        bytes[1023..1026].copy_from_slice(&[0x1f, 0x8b, 0x08]);
+        let (spans, mut windows) = collect(
+            &bytes[..],
+            r"\x1f\x8b\x08",
+            3,
+            &SearchOptions {
+                margins: Some((16, 16)),
+                buffer_size: 1024,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(spans, vec![1023..1026]);
+        assert_eq!(read_bytes(&mut windows, 1023, 3), vec![0x1f, 0x8b, 0x08]);
+        assert_eq!(read_bytes(&mut windows, 1007, 35).len(), 35);
+        assert!(read_bytes(&mut windows, 1006, 1).is_empty());
+    }
+
+    #[test]
+    fn test_search_regex_align() {
+        let bytes = [0; 16];
+        let (spans, _) = collect(
+            &bytes[..],
+            r"\x00\x00\x00",
+            3,
+            &SearchOptions {
+                align: Some((4, 1)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        // the aligned matches overlapping the unaligned ones are found as well
+        assert_eq!(spans, vec![1..4, 5..8, 9..12, 13..16]);
+    }
+
+    #[test]
+    fn test_seekable_len() {
+        // the length is measured by seeking, and the position is back to the beginning
+        let mut input = io::Cursor::new(vec![0; 512]);
+        input.set_position(100);
+        assert_eq!(seekable_len(&mut input), Some(512));
+        assert_eq!(input.position(), 0);
+        // nothing to read at the beginning
+        assert_eq!(seekable_len(io::Cursor::new(Vec::new())), None);
+    }
+
+    #[test]
+    fn test_within_distance() {
+        let offsets = [0x10, 0x100, 0x200, 0x300];
+        assert_eq!(
+            within_distance(&offsets, &[0x140, 0x2f0], 0x40),
+            vec![0x100, 0x300]
+        );
+        assert_eq!(within_distance(&offsets, &[0x10], 0), vec![0x10]);
+        assert!(within_distance(&offsets, &[0x400], 0x40).is_empty());
+    }
+
+    #[test]
+    fn test_followed_within() {
+        let offsets = [0x100, 0x200, 0x300];
+        let others = [0x0f0, 0x140, 0x180, 0x2f0];
+        // the nearest one of the others on the side of the distance, inclusive of its end
+        assert_eq!(followed_within(&offsets, &others, 0x40), vec![(0, 1)]);
+        assert_eq!(
+            followed_within(&offsets, &others, -0x10),
+            vec![(0, 0), (2, 3)]
+        );
+        assert_eq!(
+            followed_within(&offsets, &others, 0x100),
+            vec![(0, 1), (1, 3)]
+        );
+        assert!(followed_within(&offsets, &others, 0x3f).is_empty());
+        assert!(followed_within(&offsets, &[], 0x100).is_empty());
+    }
+
+    #[test]
+    fn test_search_error() {
+        let data = io::Cursor::new(b"\x1f\x8b\x09");
+        let (spans, _) = collect(data.clone(), r"\x1f\x8b\x08", 3, &Default::default()).unwrap();
+        assert!(spans.is_empty());
+        assert!(matches!(
+            collect(data, r"(\x1f", 3, &Default::default()),
+            Err(SearchError::InvalidPattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_search_float() {
+        let mut data = vec![0; 2048];
+        data[1022..1026].copy_from_slice(&1.25_f32.to_le_bytes());
+        data[1500..1504].copy_from_slice(&1.2501_f32.to_le_bytes());
+        let spans = search_float(
+            io::Cursor::new(&data),
+            4,
+            true,
+            (1.25, 0.001),
+            &SearchOptions {
+                buffer_size: 1024,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(spans, vec![1022..1026, 1500..1504]);
+        assert_eq!(
+            decode_float(&data[1500..1504], true),
+            Some(1.2501_f32 as f64)
+        );
+        assert!(search_float(
+            io::Cursor::new(&data),
+            4,
+            false,
+            (1.25, 0.001),
+            &SearchOptions::default(),
+        )
+        .unwrap()
+        .is_empty());
+
+        // stop reading after the chunk of the first match with max_count 1
+        data.resize(4096, 0);
+        let mut reader = io::Cursor::new(&data);
+        let spans = search_float(
+            &mut reader,
+            4,
+            true,
+            (1.25, 0.001),
+            &SearchOptions {
+                buffer_size: 1024,
+                max_count: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(spans, vec![1022..1026]);
+        assert_eq!(reader.position(), 2048);
+    }
+}
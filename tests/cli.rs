@@ -0,0 +1,1676 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const VMLINUZ: &str = "tests/data/vmlinuz-6.4-x86_64";
+
+// A directory for the files of a test, which is removed once the test ends even if it fails
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("hexsearch-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn hexsearch(args: &[&str]) -> std::process::Output {
+    // there is no hexsearch/config.toml in tests/data to pick up
+    Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+        .env("XDG_CONFIG_HOME", "tests/data")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_print0() {
+    let output = hexsearch(&["--print0", "-o", "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    let record = |offset| format!("{VMLINUZ}: {offset}: 1f 8b 08\0");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        record("0061bd72") + &record("006b7b9e") + &record("0085ab9f")
+    );
+}
+
+#[test]
+fn test_print0_rejected_in_hexdump() {
+    let output = hexsearch(&["--print0", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_regex_buffer_size() {
+    // the overlap around the chunk boundaries isn't shrunk to fit the buffer
+    let output = hexsearch(&[
+        "--regex",
+        "--buffer-size",
+        "8",
+        "-o",
+        "\\x1f.{3,10}",
+        VMLINUZ,
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("use --buffer-size 11 or larger"));
+}
+
+#[test]
+fn test_closed_stderr() {
+    // the summary to a closed stderr exits like the output to a closed stdout, without a panic
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+        .args(["-o", "1f 8b 08", VMLINUZ])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stderr.take());
+    let output = child.wait_with_output().unwrap();
+    assert_ne!(output.status.code(), Some(101));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+}
+
+#[test]
+fn test_octal_and_decimal() {
+    for args in [["--octal", "037 213 010"], ["--decimal", "31 139 8"]] {
+        let output = hexsearch(&[&["-o"], &args[..], &[VMLINUZ]].concat());
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with(&format!("{VMLINUZ}: 0061bd72: 1f 8b 08\n")));
+    }
+
+    // the values out of a byte are rejected
+    let output = hexsearch(&["--octal", "037 400", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("400 isn't an octal byte."));
+    let output = hexsearch(&["--decimal", "31 256", VMLINUZ]);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("256 isn't a decimal byte."));
+}
+
+#[test]
+fn test_reverse_search() {
+    let output = hexsearch(&["--reverse-search", "-o", "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+}
+
+#[test]
+fn test_multiple_patterns() {
+    let output = hexsearch(&["-p", "1f 8b 08", "-p", "4d 5a 90", "-o", VMLINUZ]);
+    assert!(output.status.success());
+    let record = |offset, bytes| format!("{VMLINUZ}: {offset}: {bytes} pattern: {bytes}\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        record("003e891f", "4d 5a 90")
+            + &record("0061bd72", "1f 8b 08")
+            + &record("006b7b9e", "1f 8b 08")
+            + &record("0085ab9f", "1f 8b 08")
+            + &record("00ae67ec", "4d 5a 90")
+    );
+}
+
+#[test]
+fn test_decompress() {
+    // 64 lines of "NNN: The quick brown fox jumps over the lazy dog\n"
+    ["tests/data/fox.txt.gz", "tests/data/fox.txt.xz"]
+        .iter()
+        .for_each(|path| {
+            let output = hexsearch(&["-Z", "--format", "csv", "66 6f 78", path]);
+            assert!(output.status.success());
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert_eq!(stdout.lines().count(), 1 + 64);
+            assert_eq!(
+                stdout.lines().nth(64).unwrap(),
+                format!("{path},3108,00000c24,3,666f78")
+            );
+        });
+}
+
+#[test]
+fn test_not_found_message() {
+    let output = hexsearch(&["1F 8B 09 FF", VMLINUZ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with(&format!(
+            "Cannot find the bytes in {VMLINUZ}: 1F 8B 09 FF\n"
+        )));
+}
+
+// /proc/self/mem opens but fails to read at offset 0, which isn't mapped
+#[cfg(target_os = "linux")]
+#[test]
+fn test_read_error() {
+    let output = hexsearch(&["1F 8B 09 FF", "/proc/self/mem"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Failed to read /proc/self/mem: "));
+}
+
+// /proc/self/status reports 0 bytes but has the status of hexsearch
+#[cfg(target_os = "linux")]
+#[test]
+fn test_pseudo_file() {
+    let output = hexsearch(&["-A", "1", "-w", "8", "'Name:'", "/proc/self/status"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("|Name:.he|\n00000008  78 73 65 61  72 63 68 0a  |xsearch.|\n"));
+
+    let output = hexsearch(&["--extract", "auto", "'Name:'", "/proc/self/status"]);
+    assert!(output.stdout.starts_with(b"Name:\thexsearch\n"));
+    assert!(output.stdout.ends_with(b"\n"));
+}
+
+#[test]
+fn test_file_footer() {
+    let args = [
+        "--file-footer",
+        "1f 8b 08",
+        VMLINUZ,
+        "tests/data/fox.txt.gz",
+    ];
+    let output = hexsearch(&args);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "--- 3 match(es) found in {VMLINUZ} (12723040 bytes) ---\n\
+             Cannot find the bytes in tests/data/fox.txt.gz: 1f 8b 08\n\n\
+             --- 3 match(es) in 1 of 2 file(s) ---\n"
+        )
+    );
+
+    // the footer is optional and suppressed by --quiet
+    let output = hexsearch(&args[1..]);
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("found in"));
+    let output = hexsearch(&[&["-q"], &args[..]].concat());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_replace() {
+    let dir = TempDir::new("replace");
+    let path = dir.join("patch.bin");
+    std::fs::write(&path, b"\x00\x1f\x8b\x08\x00\x1f\x8b\x08").unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = hexsearch(&["--replace", "0x4d5a90", "--dry-run", "1f 8b 08", path]);
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read(path).unwrap(),
+        b"\x00\x1f\x8b\x08\x00\x1f\x8b\x08"
+    );
+
+    let output = hexsearch(&["--replace", "0x4d5a90", "1f 8b 08", path]);
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read(path).unwrap(),
+        b"\x00\x4d\x5a\x90\x00\x4d\x5a\x90"
+    );
+    assert_eq!(
+        std::fs::read(format!("{path}.bak")).unwrap(),
+        b"\x00\x1f\x8b\x08\x00\x1f\x8b\x08"
+    );
+
+    // the backup of the first run isn't overwritten unless forced
+    let output = hexsearch(&["--replace", "1f 8b 08", "4d 5a 90", path]);
+    assert!(!output.status.success());
+    assert_eq!(
+        std::fs::read(path).unwrap(),
+        b"\x00\x4d\x5a\x90\x00\x4d\x5a\x90"
+    );
+    let output = hexsearch(&["--replace", "1f 8b 08", "--force", "4d 5a 90", path]);
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read(format!("{path}.bak")).unwrap(),
+        b"\x00\x4d\x5a\x90\x00\x4d\x5a\x90"
+    );
+
+    // the replacement with another length is refused, before the file is opened for
+    // the fixed bytes, or for each file of the regex matching another length
+    let output = hexsearch(&["--replace", "90 90", "--no-backup", "1f 8b 08", path]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("The replacement has 2 bytes but the pattern has 3 bytes."));
+    let output = hexsearch(&[
+        "--replace",
+        "90 90",
+        "--no-backup",
+        "--regex",
+        r"\x1f\x8b.",
+        path,
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(
+        std::fs::read(path).unwrap(),
+        b"\x00\x1f\x8b\x08\x00\x1f\x8b\x08"
+    );
+}
+
+#[test]
+fn test_csv_delimiter() {
+    let output = hexsearch(&[
+        "--format",
+        "csv",
+        "--delimiter",
+        "tab",
+        "--before-bytes",
+        "2",
+        "--after-bytes",
+        "3",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines.next(),
+        Some("file\toffset_dec\toffset_hex\tlength\tmatched_bytes_hex\tcontext_hex")
+    );
+    assert_eq!(
+        lines.next(),
+        Some(format!("{VMLINUZ}\t6405490\t0061bd72\t3\t1f8b08\t53 54 1f 8b 08 02 03 8c").as_str())
+    );
+
+    let output = hexsearch(&["--format", "csv", "--no-header", "1f 8b 08", VMLINUZ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ},6405490,0061bd72,3,1f8b08\n")));
+
+    let output = hexsearch(&["--no-header", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_decompress_gz_by_default() {
+    let output = hexsearch(&["--format", "csv", "66 6f 78", "tests/data/fox.txt.gz"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().lines().count(),
+        1 + 64
+    );
+
+    // the magic bytes of gzip are only in the compressed bytes
+    let output = hexsearch(&["-o", "1f 8b 08", "tests/data/fox.txt.gz"]);
+    assert!(!output.status.success());
+    let output = hexsearch(&["--no-decompress", "-o", "1f 8b 08", "tests/data/fox.txt.gz"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "tests/data/fox.txt.gz: 00000000: 1f 8b 08\n"
+    );
+}
+
+#[test]
+fn test_odd_hex_digits() {
+    // the value is left-padded with a 0 like a number, and then swapped in little endian
+    for (value, big, little) in [
+        ("0x1", r"\x01", r"\x01"),
+        ("0x123", r"\x01\x23", r"\x23\x01"),
+        ("0x12345", r"\x01\x23\x45", r"\x45\x23\x01"),
+        ("0x1234567", r"\x01\x23\x45\x67", r"\x67\x45\x23\x01"),
+    ] {
+        for (endian, regex) in [("big", big), ("little", little)] {
+            let output = hexsearch(&["--validate", "--endian", endian, value]);
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                format!("Pattern is valid: {regex}\n")
+            );
+        }
+    }
+}
+
+#[test]
+fn test_validate() {
+    let output = hexsearch(&["--validate", "1f 8b ??"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Pattern is valid: \\x1f\\x8b(?s:.)\n");
+
+    // the malformed bytes are invalid as well as a regex rejected by the engine
+    for bytes in ["zz", "(ff", "u32:0x1"] {
+        let output = hexsearch(&["--validate", bytes]);
+        assert_eq!(output.status.code(), Some(2), "{bytes}");
+        assert!(output.stdout.is_empty());
+    }
+
+    // the original input and the regex built from it, and then the error of the regex engine
+    let output = hexsearch(&["--validate", "--regex", r"\x1f("]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Invalid pattern: \\x1f(\nregex: \\x1f(\nregex parse error:\n"));
+}
+
+#[test]
+fn test_line_offset_only() {
+    let output = hexsearch(&["--line-offset-only", "1f 8b 08", VMLINUZ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0x0061bd70\n{VMLINUZ}: 0x006b7b90\n")));
+    let output = hexsearch(&["--line-offset-only", "-w", "32", "1f 8b 08", VMLINUZ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0x0061bd60\n")));
+
+    let output = hexsearch(&["--line-offset-only", "-c", "1", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_matches_in_one_line() {
+    let dir = TempDir::new("one-line");
+    let path = dir.join("dump.bin");
+    std::fs::write(&path, b"xxxxxxxxxxABCDABCDxxxxxxxxxxxxxx").unwrap();
+
+    // the line having both matches is printed once with both of them highlighted
+    let output = hexsearch(&[
+        "--no-color",
+        "-w",
+        "16",
+        "41 42 43 44",
+        path.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("00000000  ").count(), 1);
+    assert!(stdout.contains(
+        "00000000  78 78 78 78 78 78 78 78  78 78>41 42 43 44 41 42< |xxxxxxxxxx*A*B*C*D*A*B*|\n\
+         00000010 >43 44<"
+    ));
+}
+
+#[test]
+fn test_extract() {
+    let dir = TempDir::new("extract");
+    let path = dir.join("dump.bin");
+    let out = dir.join("out");
+    std::fs::write(&path, b"\x00\x1f\x8b\x08\x01\x02\x1f\x8b\x08\x03").unwrap();
+    let args = |length| {
+        [
+            "--extract",
+            length,
+            "--extract-dir",
+            out.to_str().unwrap(),
+            "1f 8b 08",
+            path.to_str().unwrap(),
+        ]
+    };
+
+    let output = hexsearch(&args("auto"));
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read(out.join("dump.bin_00000001.bin")).unwrap(),
+        b"\x1f\x8b\x08\x01\x02"
+    );
+    assert_eq!(
+        std::fs::read(out.join("dump.bin_00000006.bin")).unwrap(),
+        b"\x1f\x8b\x08\x03"
+    );
+
+    // the existing files are kept without --force
+    let output = hexsearch(&args("2"));
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Refuse to overwrite"));
+    assert_eq!(
+        std::fs::read(out.join("dump.bin_00000006.bin")).unwrap(),
+        b"\x1f\x8b\x08\x03"
+    );
+    // a file which can't be written fails the run as well
+    std::fs::remove_file(out.join("dump.bin_00000006.bin")).unwrap();
+    std::fs::create_dir(out.join("dump.bin_00000006.bin")).unwrap();
+    let output = hexsearch(&[&args("2")[..], &["--force"]].concat());
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Failed to write"));
+    assert_eq!(
+        std::fs::read(out.join("dump.bin_00000001.bin")).unwrap(),
+        b"\x1f\x8b"
+    );
+
+    // the raw bytes are written to stdout without --extract-dir
+    let output = hexsearch(&["--extract", "auto", "1f 8b 08", path.to_str().unwrap()]);
+    assert_eq!(output.stdout, b"\x1f\x8b\x08\x01\x02\x1f\x8b\x08\x03");
+    let output = hexsearch(&[
+        "--extract",
+        "match",
+        "--extract-separator",
+        "0d 0a",
+        "1f 8b 08",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(output.stdout, b"\x1f\x8b\x08\r\n\x1f\x8b\x08");
+}
+
+#[test]
+fn test_dump() {
+    let output = hexsearch(&["--dump", "0x61bd70", "0x61bd78", VMLINUZ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{VMLINUZ}:\n\n0061bd70  53 54 1f 8b 08 02 03 8c                           |ST......        |\n\n")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_follow() {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    let dir = TempDir::new("follow");
+    let path = dir.join("serial.log");
+    std::fs::write(&path, b"xx\x1f\x8b\x08yy").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+        .env("XDG_CONFIG_HOME", "tests/data")
+        .args([
+            "-F",
+            "--poll-interval",
+            "50",
+            "--format",
+            "grep",
+            "1f 8b 08",
+        ])
+        .arg(&path)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // wait for each line of the output as it's printed rather than for fixed periods
+    let (sender, receiver) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            sender.send(line.unwrap()).unwrap();
+        }
+    });
+    let next_line = || receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    let append = |bytes: &[u8]| {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(bytes).unwrap();
+    };
+    let path = path.to_str().unwrap();
+
+    assert_eq!(next_line(), format!("{path}:0x00000002: 1f 8b 08 |...|"));
+    // the second match may span the bytes appended at two times
+    append(b"zz\x1f");
+    thread::sleep(Duration::from_millis(100));
+    append(b"\x8b\x08");
+    assert_eq!(next_line(), format!("{path}:0x00000009: 1f 8b 08 |...|"));
+    // the file truncated is searched again from the beginning
+    std::fs::write(path, b"\x1f\x8b\x08").unwrap();
+    assert_eq!(next_line(), format!("{path}:0x00000000: 1f 8b 08 |...|"));
+
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!(
+        "{path} is truncated, search it again from the beginning.\n"
+    )));
+    assert!(stderr.ends_with("--- 3 match(es) in 1 of 1 file(s) ---\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_interrupted() {
+    use std::{thread::sleep, time::Duration};
+
+    // /dev/zero never ends, so the search only stops when interrupted
+    let child = Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+        .env("XDG_CONFIG_HOME", "tests/data")
+        .args(["ff", "/dev/zero"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    sleep(Duration::from_millis(300));
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(130));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Interrupted after scanning "));
+    assert!(stderr.ends_with(" bytes of /dev/zero, 0 match(es) so far.\n"));
+}
+
+#[test]
+fn test_generate_completions() {
+    let output = hexsearch(&["--generate-completions", "bash"]);
+    assert!(output.status.success());
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("--endian"));
+    assert!(script.contains("big little"));
+
+    let output = hexsearch(&["--generate-completion", "zsh"]);
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.contains("'*::files -- files to search:_files'"));
+}
+
+#[test]
+fn test_buffer_size() {
+    let dir = TempDir::new("buffer");
+    let path = dir.join("tiny.bin");
+    let mut data = vec![0; 1100];
+    data[3..6].copy_from_slice(b"\x1f\x8b\x08");
+    data[1024..1027].copy_from_slice(b"\x1f\x8b\x08");
+    std::fs::write(&path, data).unwrap();
+    let path = path.to_str().unwrap();
+
+    // the matches are found across the boundaries of the 4-byte chunks
+    let output = hexsearch(&["--buffer-size", "4", "-o", "1f 8b 08", path]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{path}: 00000003: 1f 8b 08\n{path}: 00000400: 1f 8b 08\n")
+    );
+
+    let output = hexsearch(&["--buffer-size", "2", "1f 8b 08", path]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("use --buffer-size 3 or larger"));
+}
+
+#[test]
+fn test_multiple_patterns_legend() {
+    let output = hexsearch(&["-p", "1f 8b 08", "-p", "4d 5a 90", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!(
+        "{VMLINUZ}:\npatterns: [1] 1f 8b 08, [2] 4d 5a 90\n\n"
+    )));
+    assert!(stdout.contains("offset: 6405490 (0061bd72), length: 3 pattern: [1] 1f 8b 08\n"));
+}
+
+#[test]
+fn test_palette() {
+    let output = hexsearch(&[
+        "--color",
+        "always",
+        "--palette",
+        "cyan,208",
+        "-p",
+        "1f 8b 08",
+        "-p",
+        "4d 5a 90",
+        VMLINUZ,
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\x1B[96m1f 8b 08"));
+    assert!(stdout.contains("\x1B[38;5;208m4d 5a 90"));
+    assert!(!stdout.contains("\x1B[91m"));
+}
+
+#[test]
+fn test_config() {
+    let dir = TempDir::new("config");
+    std::fs::create_dir_all(dir.join("hexsearch")).unwrap();
+    std::fs::write(
+        dir.join("hexsearch").join("config.toml"),
+        "only_matching = true\nbase_address = \"0x1000\"\n",
+    )
+    .unwrap();
+    let hexsearch = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+            .env("XDG_CONFIG_HOME", &*dir)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = hexsearch(&["1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0061cd72: 1f 8b 08\n")));
+    // the command line overrides the config file
+    let output = hexsearch(&["--base-address", "0", "1f 8b 08", VMLINUZ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0061bd72: 1f 8b 08\n")));
+    let output = hexsearch(&["--no-config", "1f 8b 08", VMLINUZ]);
+    assert!(!String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: ")));
+}
+
+#[test]
+fn test_labels() {
+    let output = hexsearch(&[
+        "-p",
+        "1f 8b 08",
+        "--label",
+        "gzip_magic",
+        "-p",
+        "4d 5a 90",
+        "-o",
+        VMLINUZ,
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!(
+        "{VMLINUZ}: 003e891f: 4d 5a 90 [4d 5a 90]\n{VMLINUZ}: 0061bd72: 1f 8b 08 [gzip_magic]\n"
+    )));
+
+    let output = hexsearch(&["--label", "gzip_magic", "-p", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--label gzip_magic must follow a --pattern."));
+}
+
+#[test]
+fn test_invalid_prefixed_bytes() {
+    // a single byte is validated as well as the longer ones
+    for bytes in ["0xZZ", "0x1fZZ", "0x+f", "0xé"] {
+        let output = hexsearch(&[bytes, VMLINUZ]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains(&format!(" isn't a hexadecimal byte in {bytes}.")));
+    }
+    // so are the bytes in the other formats and the mask
+    for args in [
+        &["zz", VMLINUZ][..],
+        &["ff/zz", VMLINUZ],
+        &["'GIF", VMLINUZ],
+        &["--octal", "037 400", VMLINUZ],
+        &["--decimal", "31 x", VMLINUZ],
+        &["-e", "little", "--word-size", "2", "1f 8b 08", VMLINUZ],
+        &["--mask", "ff zz", "1f 8b", VMLINUZ],
+        &["--mask", "ff", "1f 8b", VMLINUZ],
+        &["--mask", "ff ??", "1f 8b", VMLINUZ],
+    ] {
+        let output = hexsearch(args);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+    }
+    let output = hexsearch(&["-o", "-e", "little", "0x088b1f", VMLINUZ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0061bd72: 1f 8b 08\n")));
+}
+
+#[test]
+fn test_quoted_bytes() {
+    let output = hexsearch(&["-o", "'Use a' 20 \"boot\"", VMLINUZ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .ends_with(": 55 73 65 20 61 20 62 6f 6f 74\n"));
+    let output = hexsearch(&["00 'GIF", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'GIF isn't terminated by '."));
+}
+
+#[test]
+fn test_needle_file() {
+    let dir = TempDir::new("needle");
+    let needle = dir.join("needle.bin");
+    let bytes = std::fs::read(VMLINUZ).unwrap();
+    std::fs::write(&needle, &bytes[0x61bd72..0x61bd72 + 0x10000]).unwrap();
+    let needle = needle.to_str().unwrap();
+
+    let output = hexsearch(&["--needle-file", needle, "--format", "grep", VMLINUZ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}:0x0061bd72: 1f 8b 08 ")));
+    // only the first 3 bytes of the needle
+    let output = hexsearch(&[
+        "--needle-file",
+        needle,
+        "--needle-range",
+        "0:3",
+        "-o",
+        VMLINUZ,
+    ]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+    let output = hexsearch(&[
+        "--needle-file",
+        needle,
+        "--needle-range",
+        "0xffff:2",
+        VMLINUZ,
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_format_xxd() {
+    // the same as: xxd -l 10 vmlinuz-6.4-x86_64
+    let output = hexsearch(&["--format", "xxd", "--dump", "0", "10", VMLINUZ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{VMLINUZ}:\n\n00000000: 4d5a ea07 00c0 078c c88e                 MZ........\n\n")
+    );
+}
+
+#[test]
+fn test_output_file() {
+    let dir = TempDir::new("output");
+    let output_file = dir.join("output.txt");
+    let output_file = output_file.to_str().unwrap();
+
+    let output = hexsearch(&["--output-file", output_file, "-o", "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        std::fs::read_to_string(output_file)
+            .unwrap()
+            .lines()
+            .count(),
+        3
+    );
+    let output = hexsearch(&[
+        "--output-file",
+        output_file,
+        "--append",
+        "-o",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(output_file)
+            .unwrap()
+            .lines()
+            .count(),
+        6
+    );
+}
+
+#[test]
+fn test_struct() {
+    let output = hexsearch(&["-o", "--struct", "u16_be:0x1f8b u8:8 u8:*", VMLINUZ]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{VMLINUZ}: 0061bd72: 1f 8b 08 02\n{VMLINUZ}: 006b7b9e: 1f 8b 08 bc\n{VMLINUZ}: 0085ab9f: 1f 8b 08 a4\n"
+        )
+    );
+}
+
+#[test]
+fn test_uuid() {
+    // the disk GUID of the GPT header at LBA 1 is stored in the mixed endianness
+    let uuid = "01234567-89ab-cdef-0123-456789abcdef";
+    let output = hexsearch(&["--uuid", uuid, "-o", "tests/data/gpt-header.bin"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "tests/data/gpt-header.bin: 00000238: 67 45 23 01 ab 89 ef cd 01 23 45 67 89 ab cd ef\n"
+    );
+    let output = hexsearch(&[
+        "--uuid",
+        uuid,
+        "--uuid-layout",
+        "be",
+        "tests/data/gpt-header.bin",
+    ]);
+    assert!(!output.status.success());
+    let output = hexsearch(&[
+        "--uuid",
+        "01234567-89ab-cdef-0123",
+        "tests/data/gpt-header.bin",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_export_offsets() {
+    let dir = TempDir::new("offsets");
+    let offsets = dir.join("offsets.txt");
+    let offsets = offsets.to_str().unwrap();
+
+    let output = hexsearch(&["--export-offsets", offsets, "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("offset: 6405490 (0061bd72)"));
+    assert_eq!(
+        std::fs::read_to_string(offsets).unwrap(),
+        "0x61bd72\n0x6b7b9e\n0x85ab9f\n"
+    );
+    let output = hexsearch(&[
+        "--export-offsets",
+        offsets,
+        "--offset-base",
+        "dec",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string(offsets).unwrap(),
+        "6405490\n7043998\n8760223\n"
+    );
+}
+
+#[test]
+fn test_all() {
+    // the first match of each pattern in the order of offsets
+    let output = hexsearch(&[
+        "--all",
+        "--format",
+        "grep",
+        "-p",
+        "1f 8b 08",
+        "-p",
+        "4d 5a",
+        VMLINUZ,
+        "tests/data/gpt-header.bin",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "{VMLINUZ}:0x00000000: 4d 5a |MZ| pattern: 4d 5a\n\
+             {VMLINUZ}:0x0061bd72: 1f 8b 08 |...| pattern: 1f 8b 08\n"
+        )
+    );
+    let output = hexsearch(&["--all", "-p", "1f 8b 08", "-p", "'no such text'", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_codepoint() {
+    let output = hexsearch(&[
+        "--codepoint",
+        "U+0055",
+        "--codepoint",
+        "U+0073",
+        "--codepoint",
+        "U+0065",
+        "-o",
+        VMLINUZ,
+    ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 00000040: 55 73 65\n")));
+    let output = hexsearch(&["--codepoint", "U+DFFF", VMLINUZ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_then() {
+    // "Use a boot loader." at 0x40, where "boot" starts 6 bytes after "Use a"
+    let output = hexsearch(&[
+        "--then", "'boot'", "--within", "6", "--format", "grep", "'Use a'", VMLINUZ,
+    ]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{VMLINUZ}:0x00000040: 55 73 65 20 61 |Use a| then: 70 (00000046)\n")
+    );
+    let output = hexsearch(&["--then", "'boot'", "--within", "5", "'Use a'", VMLINUZ]);
+    assert!(!output.status.success());
+    let output = hexsearch(&[
+        "--then", "'Use a'", "--within", "-6", "-o", "'boot'", VMLINUZ,
+    ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!(
+            "{VMLINUZ}: 00000046: 62 6f 6f 74 then: 64 (00000040)\n"
+        )));
+}
+
+#[test]
+fn test_base64_pattern() {
+    let output = hexsearch(&["--base64-pattern", "H4sI", "-o", VMLINUZ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0061bd72: 1f 8b 08\n")));
+    let output = hexsearch(&["--base64-pattern", "H4s!", VMLINUZ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_word_size() {
+    let output = hexsearch(&[
+        "-o",
+        "-e",
+        "little",
+        "--word-size",
+        "4",
+        "08 8b 1f 54",
+        VMLINUZ,
+    ]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .starts_with(&format!("{VMLINUZ}: 0061bd71: 54 1f 8b 08\n")));
+    // only in little endian
+    let output = hexsearch(&["-o", "--word-size", "4", "08 8b 1f 54", VMLINUZ]);
+    assert!(!output.status.success());
+    let output = hexsearch(&["-e", "little", "--word-size", "2", "8b 1f 08", VMLINUZ]);
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("The 3 bytes of 8b 1f 08 aren't words of 2 bytes."));
+}
+
+#[test]
+fn test_skip() {
+    // the skipped matches aren't counted in each file
+    let output = hexsearch(&["--skip", "2", "-o", "-p", "1f 8b 08", VMLINUZ, VMLINUZ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{VMLINUZ}: 0085ab9f: 1f 8b 08\n{VMLINUZ}: 0085ab9f: 1f 8b 08\n")
+    );
+    let output = hexsearch(&["--skip", "3", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(!String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("offset:"));
+}
+
+#[test]
+fn test_byte_frequency() {
+    let output = hexsearch(&[
+        "--byte-frequency",
+        "--after-bytes",
+        "4",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    assert!(String::from_utf8(output.stdout).unwrap().contains(
+        "|......<         |\nbyte frequency:\n02: 1  03: 1  08: 1  1f: 1  3c: 1  8b: 1  8c: 1\n"
+    ));
+}
+
+#[test]
+fn test_diff() {
+    let dir = TempDir::new("diff");
+    let (old, new) = (dir.join("old.bin"), dir.join("new.bin"));
+    let mut bytes = vec![0; 64];
+    std::fs::write(&old, &bytes).unwrap();
+    bytes[0x24] = b'A';
+    std::fs::write(&new, &bytes).unwrap();
+    let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+    let output = hexsearch(&["--diff", old, new, "-c", "1", "-w", "16"]);
+    assert_eq!(output.status.code(), Some(1));
+    let zeros = "00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|";
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "< {old}\n> {new}\n\n  00000010  {zeros}\n< 00000020  {zeros}\n\
+             > 00000020  00 00 00 00 41 00 00 00  00 00 00 00 00 00 00 00  |....A...........|\n\
+             \x20 00000030  {zeros}\n"
+        )
+    );
+
+    let output = hexsearch(&["--diff", old, old]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_verbose() {
+    let quiet = hexsearch(&["-o", "1f 8b 08", VMLINUZ]);
+    let output = hexsearch(&["-vv", "-o", "1f 8b 08", VMLINUZ]);
+    // nothing but the matches goes to stdout
+    assert_eq!(output.stdout, quiet.stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("pattern: \\x1f\\x8b\\x08\n"));
+    assert!(stderr.contains(", 3 matches, engine=memmem, buffer=1MiB\n"));
+    assert!(stderr.contains("rewind around the chunk boundary"));
+}
+
+#[test]
+fn test_width_1() {
+    let output = hexsearch(&["--width", "1", "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for (offsets, line) in [
+        (
+            ["0061bd72", "0061bd73", "0061bd74"],
+            "offset: 6405490 (0061bd72), length: 3",
+        ),
+        (
+            ["006b7b9e", "006b7b9f", "006b7ba0"],
+            "offset: 7043998 (006b7b9e), length: 3",
+        ),
+        (
+            ["0085ab9f", "0085aba0", "0085aba1"],
+            "offset: 8760223 (0085ab9f), length: 3",
+        ),
+    ] {
+        let block = format!(
+            "{line}\n{}  1f  |.|\n{}  8b  |.|\n{}  08  |.|\n",
+            offsets[0], offsets[1], offsets[2]
+        );
+        assert!(stdout.contains(&block), "{block} isn't in {stdout}");
+    }
+}
+
+#[test]
+fn test_show_entropy() {
+    let output = hexsearch(&["--show-entropy", "-c", "4", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("|fbO......V......|\nentropy: 6.65 bits/byte\n"));
+}
+
+#[test]
+fn test_entropy_column() {
+    // the entropy of the matched bytes without any context
+    let output = hexsearch(&["--format", "csv", "--show-entropy", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines.next(),
+        Some("file,offset_dec,offset_hex,length,matched_bytes_hex,entropy")
+    );
+    assert_eq!(
+        lines.next(),
+        Some(&*format!("{VMLINUZ},6405490,0061bd72,3,1f8b08,1.58"))
+    );
+
+    // or of the context bytes around them
+    let output = hexsearch(&[
+        "--format",
+        "csv",
+        "--show-entropy",
+        "--before-bytes",
+        "2",
+        "--after-bytes",
+        "3",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!(
+        "{VMLINUZ},6405490,0061bd72,3,1f8b08,53 54 1f 8b 08 02 03 8c,3.00\n"
+    )));
+
+    let output = hexsearch(&[
+        "--output-format",
+        "{offset_hex} {entropy}",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout, "0061bd72 1.58\n006b7b9e 1.58\n0085ab9f 1.58\n");
+}
+
+#[test]
+fn test_highlight_all() {
+    // the occurrences skipped at 9 and 11 are highlighted in the line of the match at 13
+    let output = hexsearch(&[
+        "--no-color",
+        "--skip",
+        "2",
+        "--highlight-all",
+        "8e",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "offset: 13 (0000000d), length: 1\n00000000  4d 5a ea 07 00 c0 07 8c  c8>8e<d8>8e<c0>8e<d0 31  "
+    ));
+}
+
+#[test]
+fn test_ascii_highlight() {
+    let output = hexsearch(&["--no-color", "-w", "4", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("0061bd70  53 54 >1f 8b< |ST*.*.*|\n0061bd74 >08<02  03 8c  |*.*...|\n")
+    );
+
+    let output = hexsearch(&[
+        "--ascii-highlight",
+        "#",
+        "--format",
+        "xxd",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout
+        .contains("0061bd70: 5354 >1f8b 08<02 038c 3cc9 72dc 38b2 f7f9  ST#.#.#.#...<.r.8...\n"));
+
+    // the output in a pipe isn't marked without --no-color
+    let output = hexsearch(&["-w", "4", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("0061bd70  53 54  1f 8b  |ST..|\n"));
+}
+
+#[test]
+fn test_compact() {
+    let output = hexsearch(&["--compact", "-w", "16", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // the blank lines after the header and after the last match are kept
+    assert_eq!(stdout.lines().filter(|line| line.is_empty()).count(), 2);
+    assert!(stdout.contains("|ST......<.r.8...|\noffset: 7043998 (006b7b9e), length: 3\n"));
+
+    // the separator given is still printed
+    let output = hexsearch(&["--compact", "--context-separator=--", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().filter(|line| *line == "--").count(), 2);
+}
+
+#[test]
+fn test_anchors() {
+    let only = |args: &[&str]| {
+        let output = hexsearch(&[&["-o"], args, &[VMLINUZ]].concat());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        only(&["--anchor-start", "4d 5a"]),
+        format!("{VMLINUZ}: 00000000: 4d 5a\n")
+    );
+    assert_eq!(only(&["--anchor-start", "1f 8b 08"]), "");
+    assert_eq!(
+        only(&["--anchor-end", "5f c8"]),
+        format!("{VMLINUZ}: 00c2235e: 5f c8\n")
+    );
+    // the same as the anchors in the regex, also across the chunks read
+    assert_eq!(
+        only(&["--buffer-size", "4", "--regex", r"\A\x4d|\x5f\xc8\z"]),
+        format!("{VMLINUZ}: 00000000: 4d\n{VMLINUZ}: 00c2235e: 5f c8\n")
+    );
+}
+
+#[test]
+fn test_line_numbers() {
+    let output = hexsearch(&["--line-numbers", "-w", "8", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n0061bd70   800686  53 54 1f 8b  08 02 03 8c  |ST......|\n"));
+}
+
+#[test]
+fn test_utf8_ascii() {
+    let dir = TempDir::new("utf8");
+    let path = dir.join("text.bin");
+    std::fs::write(&path, "café 中文 ok\u{0}".as_bytes()).unwrap();
+
+    let output = hexsearch(&["--utf8-ascii", "-w", "16", "'ok'", path.to_str().unwrap()]);
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("63 61 66 c3 a9 20 e4 b8  ad e6 96 87 20 6f 6b 00  |café  中 文  ok.|\n"));
+}
+
+#[test]
+fn test_max_file_size() {
+    let output = hexsearch(&["--max-file-size", "1K", "1f 8b 08", VMLINUZ]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(
+        "Skip tests/data/vmlinuz-6.4-x86_64 of 12723040 bytes larger than --max-filesize"
+    ));
+    assert!(String::from_utf8(output.stdout).unwrap().is_empty());
+
+    // the notice is suppressed by --quiet
+    let output = hexsearch(&["-q", "--max-filesize", "1K", "1f 8b 08", VMLINUZ]);
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_emit() {
+    let output = hexsearch(&["--emit", "c", "--after-bytes", "2", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(
+        "static const uint8_t data_0x0061bd72[] = {\n    0x1f, 0x8b, 0x08, 0x02, 0x03,\n};\n"
+    ));
+    assert!(!stdout.contains('\x1b'));
+
+    let output = hexsearch(&["--emit", "rust", "--color", "always", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("const DATA_0061BD72: &[u8] = b\"\\\n    \\x1f\\x8b\\x08\";\n"));
+    assert!(!stdout.contains('\x1b'));
+}
+
+#[test]
+fn test_pager_disabled_in_pipe() {
+    // the pager would fail to run, but stdout isn't a terminal
+    let output = hexsearch(&["--pager=/nonexistent/pager", "-o", "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+}
+
+#[test]
+fn test_include_exclude() {
+    // the number of files searched in --stats
+    let searched = |globs: &[&str]| {
+        let mut args = vec!["--stats", "--format", "csv", "66 6f 78"];
+        args.extend(globs);
+        args.extend([VMLINUZ, "tests/data/fox.txt.gz", "tests/data/fox.txt.xz"]);
+        let output = hexsearch(&args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        stderr
+            .lines()
+            .find_map(|line| line.strip_prefix("files searched: "))
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(searched(&[]), "3");
+    assert_eq!(searched(&["--include", "fox.*"]), "2");
+    // --exclude takes precedence over --include
+    assert_eq!(searched(&["--include", "fox.*", "--exclude", "*.xz"]), "1");
+    assert_eq!(searched(&["--exclude", "tests/*/vmlinuz-*"]), "2");
+}
+
+#[test]
+fn test_only_matching() {
+    let output = hexsearch(&["--only-matching", "1f 8b 08", VMLINUZ]);
+    assert!(output.status.success());
+    let record = |offset| format!("{VMLINUZ}: {offset}: 1f 8b 08\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        record("0061bd72") + &record("006b7b9e") + &record("0085ab9f")
+    );
+}
+
+#[test]
+fn test_null() {
+    let output = hexsearch(&[
+        "--null",
+        "--output-format",
+        "{offset_hex}",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    assert_eq!(output.stdout, b"0061bd72\x00006b7b9e\x000085ab9f\x00");
+
+    // the offset line before each hexdump is terminated with NUL as well
+    let output = hexsearch(&["--null", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 6405490 (0061bd72), length: 3\0"));
+}
+
+#[test]
+fn test_quiet() {
+    let output = hexsearch(&["-q", "1f 8b 08", VMLINUZ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+
+    let output = hexsearch(&["-q", "de ad be ef 00 11", VMLINUZ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+
+    // the error messages are only suppressed by -qq
+    let output = hexsearch(&["-q", "xyz", VMLINUZ]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "xyz isn't a hexadecimal byte.\n"
+    );
+    let output = hexsearch(&["-qq", "xyz", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_files_from() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = TempDir::new("files-from");
+    let list = dir.join("files.txt");
+    std::fs::write(&list, format!("{VMLINUZ}\ntests/data/fox.txt.gz\n")).unwrap();
+    let output = hexsearch(&["--files-from", list.to_str().unwrap(), "-o", "1f 8b 08"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 3);
+    assert!(stdout.starts_with(&format!("{VMLINUZ}: 0061bd72: 1f 8b 08\n")));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Cannot find the bytes in tests/data/fox.txt.gz: 1f 8b 08\n"));
+    assert!(stderr.ends_with("--- 3 match(es) in 1 of 2 file(s) ---\n"));
+
+    // - reads the list from stdin, which is separated by NUL with --null
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+        .env("XDG_CONFIG_HOME", "tests/data")
+        .args(["-T", "-", "--null", "--output-format", "{file}", "1f 8b 08"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    write!(child.stdin.take().unwrap(), "{VMLINUZ}\0").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{VMLINUZ}\0").repeat(3)
+    );
+}
+
+#[test]
+fn test_quiet_stops_at_first_match() {
+    // the missing file after the first match isn't opened at all
+    let output = hexsearch(&["-q", "1f 8b 08", VMLINUZ, "tests/data/missing"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+
+    // nor is the not-found message printed
+    let output = hexsearch(&["-q", "de ad be ef 00 11", VMLINUZ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_context_separator() {
+    let output = hexsearch(&["--context-separator", "==", "-c", "1", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("\n==\n").count(), 2);
+    assert!(stdout.contains("0061bd80  8a 0a f7 65 e6 60 8f 4a  b6 34 8e 78 a1 03 48 82  |...e.`.J.4.x..H.|\n==\noffset: 7043998 (006b7b9e), length: 3\n"));
+
+    // the overlapping contexts of the matches at 0x0061bd72 and 0x0061bd75 are dumped once
+    let output = hexsearch(&["-c", "1", "-p", "1f 8b 08", "-p", "02 03 8c", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "offset: 6405490 (0061bd72), length: 3 pattern: [1] 1f 8b 08\noffset: 6405493 (0061bd75), length: 3 pattern: [2] 02 03 8c\n0061bd60 "
+    ));
+    assert_eq!(stdout.matches("\n0061bd70 ").count(), 1);
+}
+
+#[test]
+fn test_context_bytes() {
+    // the dump starts at the first context byte rather than the line boundary
+    let output = hexsearch(&[
+        "--before-bytes",
+        "2",
+        "--after-bytes",
+        "3",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        "offset: 6405490 (0061bd72), length: 3\n0061bd70  53 54 1f 8b 08 02 03 8c                           |ST......        |\n"
+    ));
+    assert!(stdout.contains("\n006b7b9c  d4 ee 1f 8b 08 bc 85 91 "));
+
+    // the bytes before the start of file are dropped
+    let output = hexsearch(&[
+        "--before-bytes",
+        "8",
+        "--after-bytes",
+        "0",
+        "4d 5a",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 0 (00000000), length: 2\n00000000  4d 5a "));
+}
+
+#[test]
+fn test_hex_only_and_ascii_only() {
+    let output = hexsearch(&["--hex-only", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n0061bd70  53 54 1f 8b 08 02 03 8c  3c c9 72 dc 38 b2 f7 f9\n"));
+
+    let output = hexsearch(&["--ascii-only", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n0061bd70  |ST......<.r.8...|\n"));
+
+    let output = hexsearch(&["--hex-only", "--ascii-only", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_string_utf16() {
+    let dir = TempDir::new("utf16");
+    let path = dir.join("strings.bin");
+    // "Kernel" in UTF-16LE at 2 and in UTF-16BE at 16
+    let mut bytes = b"\xff\xfe".to_vec();
+    bytes.extend("Kernel".encode_utf16().flat_map(u16::to_le_bytes));
+    bytes.extend(b"\xfe\xff");
+    bytes.extend("Kernel".encode_utf16().flat_map(u16::to_be_bytes));
+    std::fs::write(&path, bytes).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = hexsearch(&[
+        "--string-utf16",
+        "--output-format",
+        "{offset_hex}",
+        "Kernel",
+        path,
+    ]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "00000002\n");
+    let output = hexsearch(&[
+        "--string-utf16be",
+        "--output-format",
+        "{offset_hex}",
+        "Kernel",
+        path,
+    ]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "00000010\n");
+}
+
+#[test]
+fn test_offset_width() {
+    // the offsets are padded to the digits of the largest 32-bit offset in each base
+    let output = hexsearch(&["--offset-base", "dec", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 6405490 (0006405490), length: 3\n0006405488  53 54 "));
+
+    let output = hexsearch(&["--offset-base", "oct", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 6405490 (00030336562), length: 3\n00030336560  53 54 "));
+}
+
+#[test]
+fn test_relative_offsets() {
+    let output = hexsearch(&["--relative-offsets", "-o", "1f 8b 08", VMLINUZ]);
+    let record = |offset, delta| format!("{VMLINUZ}: {offset}: 1f 8b 08 (+0x{delta})\n");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        record("0061bd72", "61bd72") + &record("006b7b9e", "9be2c") + &record("0085ab9f", "1a3001")
+    );
+}
+
+#[test]
+fn test_stats() {
+    let output = hexsearch(&[
+        "--stats",
+        "--no-decompress",
+        "-o",
+        "1f 8b 08",
+        "tests/data/fox.txt.gz",
+        "tests/data/missing",
+    ]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(
+        "files searched: 1\nfiles matched: 1\nfiles failed: 1\ntotal matches: 1\nbytes scanned: 222 (0.00 MB)\n"
+    ));
+    assert!(stderr.contains("--- 1 match(es) in 1 of 1 file(s), 1 file(s) failed ---\n"));
+    assert!(stderr.contains("\nelapsed time: "));
+    assert!(stderr.ends_with(" MB/s\n"));
+}
+
+#[test]
+fn test_dump_match() {
+    let output = hexsearch(&["--dump-match", "1f 8b 08", VMLINUZ]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0061bd72: 1f 8b 08  |...|\n006b7b9e: 1f 8b 08  |...|\n0085ab9f: 1f 8b 08  |...|\n"
+    );
+}
+
+#[test]
+fn test_width_range() {
+    for width in ["0", "65"] {
+        let output = hexsearch(&["--width", width, "1f 8b 08", VMLINUZ]);
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(&format!("{width} is not in 1..=64")));
+    }
+
+    let output = hexsearch(&["--width", "64", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("\n0061bd40  80 89 a4 10 "));
+}
+
+#[test]
+fn test_highlight() {
+    for (highlight, code) in [("bold", 1), ("underline", 4), ("invert", 7)] {
+        let output = hexsearch(&[
+            "--color",
+            "always",
+            "--highlight",
+            highlight,
+            "1f 8b 08",
+            VMLINUZ,
+        ]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let style = format!("\x1b[{code}m");
+        assert!(
+            stdout.contains(&format!(
+                "{style} 1f\x1b[0m{style} 8b\x1b[0m{style} 08\x1b[0m"
+            )),
+            "{highlight} isn't in {stdout}"
+        );
+    }
+}
+
+#[test]
+fn test_buffer_size_suffix() {
+    // the matches are found across the boundaries of the 1 KiB chunks
+    let output = hexsearch(&["--buffer-size", "1K", "-o", "1f 8b 08", VMLINUZ]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+
+    let output = hexsearch(&["--buffer-size", "1X", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("1X isn't a size in bytes optionally suffixed with K, M or G"));
+}
+
+#[test]
+fn test_progress_hidden_in_pipe() {
+    // the progress bar is only drawn when stderr is a terminal
+    let output = hexsearch(&["-o", "1f 8b 08", VMLINUZ]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "--- 3 match(es) in 1 of 1 file(s) ---\n");
+}
+
+#[test]
+fn test_base_address() {
+    // the address is added to the offset line, the hexdump and the machine output
+    let output = hexsearch(&["--base-address", "0x400000", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 10599794 (00a1bd72), length: 3\n00a1bd70  53 54 1f 8b 08 "));
+    let output = hexsearch(&[
+        "--base-address",
+        "4096",
+        "--format",
+        "csv",
+        "1f 8b 08",
+        VMLINUZ,
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("\n{VMLINUZ},6409586,0061cd72,3,1f8b08\n")));
+
+    let output = hexsearch(&["--base-address", "0xffffffffffffff00", "1f 8b 08", VMLINUZ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "The addresses of {VMLINUZ} exceed 64 bits with --base-address.\n"
+    )));
+}
+
+#[test]
+fn test_before_and_after_context() {
+    let output = hexsearch(&["-B", "1", "-A", "0", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 6405490 (0061bd72), length: 3\n0061bd60  c5 60 c3 20 "));
+    assert!(stdout.contains("  |ST......<.r.8...|\n\noffset: 7043998"));
+
+    // -A overrides the lines after the match given by -c
+    let output = hexsearch(&["-c", "2", "-A", "0", "1f 8b 08", VMLINUZ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("offset: 6405490 (0061bd72), length: 3\n0061bd50  d0 a0 10 a8 "));
+    assert!(stdout.contains("  |ST......<.r.8...|\n\noffset: 7043998"));
+}
+
+#[test]
+fn test_color() {
+    let colored = |when| {
+        let output = hexsearch(&["--color", when, "1f 8b 08", VMLINUZ]);
+        String::from_utf8(output.stdout).unwrap().contains('\x1b')
+    };
+    // stdout isn't a terminal in the tests
+    assert!(colored("always"));
+    assert!(!colored("auto"));
+    assert!(!colored("never"));
+}
+
+// script(1) runs hexsearch with stderr in a pseudo terminal to draw the progress bar
+#[cfg(target_os = "linux")]
+#[test]
+fn test_progress_file_name() {
+    let stderr = |args: &str| {
+        let command = format!("{} {args} >/dev/null", env!("CARGO_BIN_EXE_hexsearch"));
+        let output = Command::new("script")
+            .env("XDG_CONFIG_HOME", "tests/data")
+            .args(["-qec", &command, "/dev/null"])
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let output = stderr(&format!("-o '1f 8b 08' {VMLINUZ} tests/data/fox.txt.xz"));
+    assert!(output.contains(&format!("Searching {VMLINUZ}... [")));
+    assert!(output.contains("--- 3 match(es) in 1 of 2 file(s) ---"));
+
+    // the bar is hidden with --quiet
+    let output = stderr(&format!("-q -o '1f 8b 08' {VMLINUZ}"));
+    assert!(!output.contains("Searching"));
+}
+
+#[test]
+fn test_rust_log() {
+    let output = Command::new(env!("CARGO_BIN_EXE_hexsearch"))
+        .env("XDG_CONFIG_HOME", "tests/data")
+        .env("RUST_LOG", "hexsearch=debug")
+        .args(["-o", "1f 8b 08", VMLINUZ])
+        .output()
+        .unwrap();
+    // the trace goes to stderr only
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#"search forward pattern="\\x1f\\x8b\\x08" pattern_len=3"#));
+    assert!(stderr.contains("read a chunk start=0 read=1048576\n"));
+    assert!(stderr.contains("found a match offset=6405490 end=6405493\n"));
+
+    // nothing is traced without RUST_LOG
+    let output = hexsearch(&["-o", "1f 8b 08", VMLINUZ]);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("DEBUG"));
+}